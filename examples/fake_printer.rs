@@ -0,0 +1,150 @@
+//! A stand-in for a network label printer, to try printing from LISA without one.
+//!
+//! ```text
+//! cargo run --example fake_printer -- [--port 9100] [--lpr] [--fail N] [--out DIR]
+//! ```
+//!
+//! Listens on `127.0.0.1` for raw jobs, or LPR jobs with `--lpr`, and saves each job it receives
+//! to `DIR`, `fake_printer_jobs` by default. With `--lpr`, `--fail N` refuses the first `N` jobs,
+//! to see LISA try again. Raw printers don't acknowledge jobs, so to see a raw job tried again,
+//! start the fake printer after LISA. Point a printer of the config at it with:
+//!
+//! ```toml
+//! [[print.printer]]
+//! name = "Fake"
+//! host = "127.0.0.1"
+//! port = 9100
+//! ```
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+struct Options {
+    port: u16,
+    lpr: bool,
+    fail: u32,
+    out: PathBuf,
+}
+
+fn options() -> Options {
+    let mut options = Options {
+        port: 9100,
+        lpr: false,
+        fail: 0,
+        out: PathBuf::from("fake_printer_jobs"),
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| panic!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--port" => options.port = value("--port").parse().expect("--port is a number"),
+            "--lpr" => options.lpr = true,
+            "--fail" => options.fail = value("--fail").parse().expect("--fail is a number"),
+            "--out" => options.out = PathBuf::from(value("--out")),
+            other => panic!("Unknown argument {}", other),
+        }
+    }
+    options
+}
+
+/// Reads a raw job, everything sent until the connection is closed.
+fn raw_job(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![];
+    stream.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Reads an LPR job, acknowledging each command, and returns its data file.
+fn lpr_job(stream: TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = vec![];
+
+    // Receive a printer job: \x02queue\n
+    reader.read_until(b'\n', &mut line)?;
+    println!("LPR queue {}", String::from_utf8_lossy(&line[1..]).trim());
+    writer.write_all(&[0])?;
+
+    let mut data = vec![];
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(data);
+        }
+        // Subcommands: \x02count cfA... for the control file, \x03count dfA... for the data file.
+        let text = String::from_utf8_lossy(&line[1..]).into_owned();
+        let count: usize = text
+            .split_whitespace()
+            .next()
+            .and_then(|count| count.parse().ok())
+            .unwrap_or_default();
+        writer.write_all(&[0])?;
+
+        let mut file = vec![0; count + 1];
+        reader.read_exact(&mut file)?;
+        file.truncate(count);
+        writer.write_all(&[0])?;
+
+        if line[0] == 3 {
+            data = file;
+        }
+    }
+}
+
+fn save(out: &Path, job: usize, data: &[u8]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(out)?;
+    let path = out.join(format!("job-{:04}.prn", job));
+    std::fs::write(&path, data)?;
+    Ok(path)
+}
+
+fn main() -> std::io::Result<()> {
+    let options = options();
+    let listener = TcpListener::bind(("127.0.0.1", options.port))?;
+    println!(
+        "Fake {} printer listening on 127.0.0.1:{}",
+        if options.lpr { "LPR" } else { "raw" },
+        options.port
+    );
+
+    let mut refused = 0;
+    let mut jobs = 0;
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        if options.lpr && refused < options.fail {
+            refused += 1;
+            println!("Refused job {} of {}", refused, options.fail);
+            // Refuse the `receive a printer job` command.
+            let mut command = [0; 256];
+            let _ = stream.read(&mut command);
+            stream.write_all(&[1])?;
+            continue;
+        }
+
+        let data = match options.lpr {
+            true => lpr_job(stream),
+            false => raw_job(&mut stream),
+        };
+        match data {
+            Ok(data) => {
+                jobs += 1;
+                let path = save(&options.out, jobs, &data)?;
+                let labels = String::from_utf8_lossy(&data).matches("^XA").count();
+                println!(
+                    "Received job {}: {} bytes, {} label(s), saved to {}",
+                    jobs,
+                    data.len(),
+                    labels,
+                    path.display()
+                );
+            }
+            Err(e) => println!("Failed to receive a job: {}", e),
+        }
+    }
+    Ok(())
+}
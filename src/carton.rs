@@ -0,0 +1,214 @@
+use crate::columns::{normalize, Column, ColumnMap, Field};
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+use serde::Deserialize;
+
+/// The name of the cartons items no [CartonRule] picks are packed in.
+pub const STANDARD: &str = "Standard";
+
+/// A kind of carton and the items packed in it, overriding the `box_size` of the profile.
+///
+/// An item is packed in the carton of the first rule it matches, by the start of its style code
+/// or by its department, for example:
+///
+/// ```toml
+/// [[profile.carton]]
+/// name = "Footwear"
+/// capacity = 12
+/// departments = ["Footwear", "410"]
+///
+/// [[profile.carton]]
+/// name = "Tees"
+/// capacity = 72
+/// styles = ["TS", "TEE"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CartonRule {
+    pub name: String,
+    /// The number of items that fit in a carton.
+    pub capacity: u32,
+    /// Style codes starting with any of these are packed in this carton.
+    #[serde(default)]
+    pub styles: Vec<String>,
+    /// Items of any of these departments are packed in this carton. Departments are compared
+    /// ignoring case, spaces and punctuation.
+    #[serde(default)]
+    pub departments: Vec<String>,
+}
+
+/// The carton an item is packed in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CartonClass {
+    pub name: String,
+    pub capacity: u32,
+}
+
+/// The carton rules of a profile, bound to the columns of a PO file.
+#[derive(Debug)]
+pub struct Cartons {
+    standard: CartonClass,
+    rules: Vec<CartonRule>,
+    style_index: Option<usize>,
+    department_index: Option<usize>,
+}
+
+impl Cartons {
+    /// Checks `rules` for a PO file with the given `columns`. Items no rule picks are packed
+    /// `box_size` to a carton.
+    ///
+    /// Fails if a rule picks items by department and the file has no `department` column.
+    pub fn new(
+        rules: &[CartonRule],
+        box_size: u32,
+        columns: &ColumnMap,
+        department: &Column,
+    ) -> Result<Cartons> {
+        if box_size == 0 {
+            bail!("`box_size` must be at least 1");
+        }
+        for rule in rules {
+            if rule.capacity == 0 {
+                bail!("The carton '{}' must hold at least 1 item", rule.name);
+            }
+            if rule.styles.is_empty() && rule.departments.is_empty() {
+                bail!(
+                    "The carton '{}' has no `styles` or `departments` to pick items by",
+                    rule.name
+                );
+            }
+        }
+
+        let department_index = match rules.iter().any(|rule| !rule.departments.is_empty()) {
+            true => Some(columns.index(department).ok_or_else(|| {
+                anyhow!(
+                    "The PO file has no department column `{}` to pick cartons by",
+                    department
+                )
+            })?),
+            false => None,
+        };
+
+        Ok(Cartons {
+            standard: CartonClass {
+                name: STANDARD.to_owned(),
+                capacity: box_size,
+            },
+            rules: rules.to_vec(),
+            style_index: columns.index(&Column::Field(Field::Style)),
+            department_index,
+        })
+    }
+
+    /// Returns the carton `record` is packed in.
+    pub fn class(&self, record: &StringRecord) -> CartonClass {
+        let value = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .unwrap_or_default()
+                .trim()
+        };
+        let style = value(self.style_index);
+        let department = normalize(value(self.department_index));
+
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.styles
+                    .iter()
+                    .any(|prefix| !prefix.is_empty() && style.starts_with(prefix.as_str()))
+                    || rule
+                        .departments
+                        .iter()
+                        .any(|name| normalize(name) == department)
+            })
+            .map_or_else(
+                || self.standard.clone(),
+                |rule| CartonClass {
+                    name: rule.name.clone(),
+                    capacity: rule.capacity,
+                },
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::Aliases;
+
+    const HEADERS: [&str; 9] = [
+        "PO",
+        "Style",
+        "Color Code",
+        "Size",
+        "Style Desc",
+        "Color Desc",
+        "UPC",
+        "Qty",
+        "Department",
+    ];
+
+    #[derive(Deserialize)]
+    struct Profile {
+        carton: Vec<CartonRule>,
+    }
+
+    fn cartons(toml: &str) -> Result<Cartons> {
+        let profile: Profile = toml::from_str(toml).unwrap();
+        let headers = StringRecord::from(HEADERS.to_vec());
+        let columns = ColumnMap::resolve(&headers, &Aliases::default()).unwrap();
+        Cartons::new(
+            &profile.carton,
+            24,
+            &columns,
+            &Column::Header("Department".to_owned()),
+        )
+    }
+
+    fn class(cartons: &Cartons, style: &str, department: &str) -> String {
+        let record = StringRecord::from(vec![
+            "po1-001", style, "001", "M", "", "", "1", "1", department,
+        ]);
+        cartons.class(&record).name
+    }
+
+    const RULES: &str = r#"
+[[carton]]
+name = "Footwear"
+capacity = 12
+departments = ["Footwear", "410"]
+
+[[carton]]
+name = "Tees"
+capacity = 72
+styles = ["TS", "TEE"]
+"#;
+
+    #[test]
+    fn picks_the_first_rule_matched() {
+        let cartons = cartons(RULES).unwrap();
+        assert_eq!(class(&cartons, "TS100", "Apparel"), "Tees");
+        assert_eq!(class(&cartons, "SH100", " foot-wear "), "Footwear");
+        // Both rules match, the first one written wins.
+        assert_eq!(class(&cartons, "TEE1", "410"), "Footwear");
+        assert_eq!(
+            cartons.class(&StringRecord::from(vec!["po1-001", "JK1"])),
+            CartonClass {
+                name: STANDARD.to_owned(),
+                capacity: 24,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_cartons_that_pick_nothing_or_hold_nothing() {
+        let error = |toml: &str| cartons(toml).unwrap_err().to_string();
+        assert!(
+            error("[[carton]]\nname = \"A\"\ncapacity = 0\nstyles = [\"A\"]\n")
+                .contains("must hold at least 1 item")
+        );
+        assert!(error("[[carton]]\nname = \"A\"\ncapacity = 6\n")
+            .contains("has no `styles` or `departments`"));
+    }
+}
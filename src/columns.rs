@@ -0,0 +1,299 @@
+use anyhow::{bail, Result};
+use csv::StringRecord;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Fields pertaining to the RFID CSV produced by the Infinity app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Po,
+    Style,
+    ColorCode,
+    Size,
+    StyleDesc,
+    ColorDesc,
+    Upc,
+    Qty,
+}
+
+impl Field {
+    /// Every field LISA needs to find in a PO file before it can process it.
+    pub const REQUIRED: [Field; 8] = [
+        Field::Po,
+        Field::Style,
+        Field::ColorCode,
+        Field::Size,
+        Field::StyleDesc,
+        Field::ColorDesc,
+        Field::Upc,
+        Field::Qty,
+    ];
+
+    /// Returns the name used for the field in error messages.
+    pub fn name(&self) -> &str {
+        match self {
+            Field::Po => "PO",
+            Field::Style => "Style",
+            Field::ColorCode => "Color Code",
+            Field::Size => "Size",
+            Field::StyleDesc => "Style Description",
+            Field::ColorDesc => "Color Description",
+            Field::Upc => "UPC",
+            Field::Qty => "Qty",
+        }
+    }
+
+    /// Returns the headers the Infinity app, and most PO exports, use for the field.
+    fn default_aliases(&self) -> &'static [&'static str] {
+        match self {
+            Field::Po => &["PO", "PO Number", "Purchase Order"],
+            Field::Style => &["Style", "Style Code", "Style Number"],
+            Field::ColorCode => &["Color Code", "Color"],
+            Field::Size => &["Size", "MSRP Size"],
+            Field::StyleDesc => &["Style Desc", "Style Description"],
+            Field::ColorDesc => &["Color Desc", "Color Description"],
+            Field::Upc => &["UPC", "UPC Code"],
+            Field::Qty => &["Qty", "Quantity", "Units"],
+        }
+    }
+}
+
+/// A column of a PO file named in the config, either one of the fields LISA knows, e.g. `upc`,
+/// or any other header of the file, e.g. `"Vendor"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Field(Field),
+    Header(String),
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Column::Field(field) => write!(f, "{}", field.name()),
+            Column::Header(header) => write!(f, "{}", header),
+        }
+    }
+}
+
+/// Extra header names that should be accepted for a field.
+///
+/// The aliases are read from the `[columns]` table of the config file and are tried in addition
+/// to the defaults of each field, for example:
+///
+/// ```toml
+/// [columns]
+/// po = ["PO#", "Purchase Order"]
+/// qty = ["Order Qty"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Aliases {
+    po: Vec<String>,
+    style: Vec<String>,
+    color_code: Vec<String>,
+    size: Vec<String>,
+    style_desc: Vec<String>,
+    color_desc: Vec<String>,
+    upc: Vec<String>,
+    qty: Vec<String>,
+}
+
+impl Aliases {
+    /// Returns the aliases of `self` followed by those of `other`.
+    pub fn merged(&self, other: &Aliases) -> Aliases {
+        let join = |a: &Vec<String>, b: &Vec<String>| [a.as_slice(), b.as_slice()].concat();
+
+        Aliases {
+            po: join(&self.po, &other.po),
+            style: join(&self.style, &other.style),
+            color_code: join(&self.color_code, &other.color_code),
+            size: join(&self.size, &other.size),
+            style_desc: join(&self.style_desc, &other.style_desc),
+            color_desc: join(&self.color_desc, &other.color_desc),
+            upc: join(&self.upc, &other.upc),
+            qty: join(&self.qty, &other.qty),
+        }
+    }
+
+    /// Returns every header name accepted for `field`, the configured aliases first.
+    fn names(&self, field: Field) -> Vec<&str> {
+        let aliases = match field {
+            Field::Po => &self.po,
+            Field::Style => &self.style,
+            Field::ColorCode => &self.color_code,
+            Field::Size => &self.size,
+            Field::StyleDesc => &self.style_desc,
+            Field::ColorDesc => &self.color_desc,
+            Field::Upc => &self.upc,
+            Field::Qty => &self.qty,
+        };
+        let mut names: Vec<&str> = aliases.iter().map(|name| name.as_str()).collect();
+        names.extend(field.default_aliases());
+        names
+    }
+}
+
+/// Header names are compared ignoring case, spaces and punctuation, so `PO#`, `po` and `P.O.`
+/// all name the same column.
+pub fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// The position of every field within the records of a PO file.
+///
+/// A `ColumnMap` is built from the header row of the file, so columns can be moved or added
+/// to the export without LISA reading the wrong data.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMap {
+    indices: HashMap<Field, usize>,
+    /// Every header of the file, normalized.
+    headers: Vec<String>,
+}
+
+impl ColumnMap {
+    /// Finds each required field in `headers`.
+    ///
+    /// Returns an error naming every required field that could not be found, or the fields
+    /// found in the same column, so the run stops before any output is written.
+    pub fn resolve(headers: &StringRecord, aliases: &Aliases) -> Result<ColumnMap> {
+        let names = headers;
+        let headers: Vec<String> = headers.iter().map(normalize).collect();
+        let mut indices = HashMap::new();
+        let mut missing = vec![];
+
+        for field in Field::REQUIRED {
+            let index = aliases
+                .names(field)
+                .into_iter()
+                .map(normalize)
+                .find_map(|name| headers.iter().position(|header| *header == name));
+
+            match index {
+                Some(index) => {
+                    indices.insert(field, index);
+                }
+                None => missing.push(field),
+            }
+        }
+
+        if !missing.is_empty() {
+            let missing = missing
+                .iter()
+                .map(|field| {
+                    format!(
+                        "{} (accepted headers: {})",
+                        field.name(),
+                        aliases.names(*field).join(", ")
+                    )
+                })
+                .collect::<Vec<String>>();
+            bail!(
+                "The PO file is missing required column(s):\n\t{}",
+                missing.join("\n\t")
+            );
+        }
+
+        // An alias can name the column of another field, which would then be read twice.
+        for (i, first) in Field::REQUIRED.iter().enumerate() {
+            for second in &Field::REQUIRED[i + 1..] {
+                if indices[first] == indices[second] {
+                    bail!(
+                        "The fields {} and {} were both found in the column `{}` of the PO file, \
+                         check the aliases of the [columns] table",
+                        first.name(),
+                        second.name(),
+                        names.get(indices[first]).unwrap_or_default()
+                    );
+                }
+            }
+        }
+
+        debug!("Resolved columns: {:?}", &indices);
+        Ok(ColumnMap { indices, headers })
+    }
+
+    /// Returns the 0-based index of `column` in the records of the PO file, or `None` if the
+    /// file has no such column.
+    pub fn index(&self, column: &Column) -> Option<usize> {
+        match column {
+            Column::Field(field) => self.indices.get(field).copied(),
+            Column::Header(header) => {
+                let header = normalize(header);
+                self.headers.iter().position(|name| *name == header)
+            }
+        }
+    }
+
+    /// Returns the 1-based column of `field` in the PO file.
+    pub fn column(&self, field: Field) -> Option<usize> {
+        self.indices.get(&field).map(|index| index + 1)
+    }
+
+    /// Returns the value of `field` in `record`.
+    ///
+    /// The CSV reader rejects records that are shorter than the header row, so every resolved
+    /// field is present in the record.
+    pub fn get<'r>(&self, record: &'r StringRecord, field: Field) -> &'r str {
+        self.indices
+            .get(&field)
+            .and_then(|index| record.get(*index))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADERS: [&str; 9] = [
+        "PO#",
+        "Style",
+        "Color Code",
+        "Size",
+        "Style Description",
+        "Color Description",
+        "UPC",
+        "Qty",
+        "Vendor",
+    ];
+
+    fn aliases(toml: &str) -> Aliases {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn resolves_headers_ignoring_case_and_punctuation() {
+        let columns =
+            ColumnMap::resolve(&StringRecord::from(HEADERS.to_vec()), &aliases("")).unwrap();
+        assert_eq!(columns.column(Field::Po), Some(1));
+        assert_eq!(columns.column(Field::Qty), Some(8));
+        assert_eq!(columns.index(&Column::Header("vendor".to_owned())), Some(8));
+    }
+
+    #[test]
+    fn names_every_missing_field() {
+        let headers = StringRecord::from(vec!["PO", "Style", "Size"]);
+        let error = ColumnMap::resolve(&headers, &aliases(""))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("UPC (accepted headers: UPC, UPC Code)"));
+        assert!(error.contains("Qty (accepted headers: Qty, Quantity, Units)"));
+        assert!(!error.contains("Style ("));
+    }
+
+    #[test]
+    fn fails_on_fields_found_in_the_same_column() {
+        let headers = StringRecord::from(HEADERS.to_vec());
+        let error = ColumnMap::resolve(&headers, &aliases("qty = [\"UPC\"]"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("The fields UPC and Qty were both found in the column `UPC`"));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::columns::Aliases;
+use crate::directory::{DirectoryConfig, StoreDirectory};
+use crate::epc::{EpcConfig, Sgtin96};
+use crate::grouping::Grouping;
+use crate::output::{OutputConfig, OutputLayout};
+use crate::print::PrintConfig;
+use crate::profile::{Profile, DEFAULT_PROFILE};
+use crate::sscc::{Sscc, SsccConfig};
+use crate::upc_list::{PreTaggedUpcs, UpcList};
+use crate::zpl::{ZplTemplate, DEFAULT_RFID_TEMPLATE};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The config file LISA looks for in the working directory when one isn't given.
+pub const DEFAULT_CONFIG: &str = "lisa.toml";
+
+/// User settings read from a TOML file.
+///
+/// Every table is optional; anything left out falls back to the defaults LISA has always used.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Extra header names accepted for each column of a PO file.
+    pub columns: Aliases,
+    /// The layouts of the PO files sent by each retailer.
+    #[serde(rename = "profile")]
+    pub profiles: Vec<Profile>,
+    /// Lists of UPCs that ship with an RFID tag from the factory.
+    #[serde(rename = "upc_list")]
+    pub upc_lists: Vec<UpcList>,
+    pub zpl: ZplConfig,
+    pub epc: EpcConfig,
+    pub print: PrintConfig,
+    pub sscc: SsccConfig,
+    pub directory: DirectoryConfig,
+    pub output: OutputConfig,
+}
+
+/// Settings of the ZPL label files, e.g.
+///
+/// ```toml
+/// [zpl]
+/// template = "labels/2x1.zpl"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZplConfig {
+    /// The label layout, see [ZplTemplate]. LISA's own layout is used without it.
+    pub template: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads the config file at `path`.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        Ok(config)
+    }
+
+    /// Returns the configured profiles followed by the built-in default profile, unless the
+    /// config defines its own profile called [DEFAULT_PROFILE].
+    pub fn all_profiles(&self) -> Vec<Profile> {
+        let mut profiles = self.profiles.clone();
+        if !profiles
+            .iter()
+            .any(|profile| profile.name == DEFAULT_PROFILE)
+        {
+            profiles.push(Profile::default());
+        }
+        profiles
+    }
+
+    /// Returns the profile called `name`.
+    pub fn profile(&self, name: &str) -> Result<Profile> {
+        self.all_profiles()
+            .into_iter()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| anyhow!("There is no profile called '{}' in the config", name))
+    }
+
+    /// Reads the UPC lists in effect today.
+    pub fn pre_tagged_upcs(&self) -> Result<PreTaggedUpcs> {
+        PreTaggedUpcs::load(&self.upc_lists, chrono::Local::now().date_naive())
+    }
+
+    /// Reads the configured ZPL label template, or returns the default one.
+    ///
+    /// When `epc` is set the labels encode an EPC, so the template must use `{epc}`, and must not
+    /// otherwise.
+    pub fn zpl_template(&self, epc: bool) -> Result<ZplTemplate> {
+        let template = match (&self.zpl.template, epc) {
+            (Some(path), _) => ZplTemplate::load(path)?,
+            (None, true) => ZplTemplate::new(DEFAULT_RFID_TEMPLATE)?,
+            (None, false) => ZplTemplate::default(),
+        };
+
+        match (template.uses("epc"), epc) {
+            (false, true) => bail!("The label template has no `{{epc}}` placeholder to encode"),
+            (true, false) => bail!("The label template encodes an `{{epc}}`, but EPCs are off"),
+            _ => Ok(template),
+        }
+    }
+
+    /// Returns the columns of the split files, set up by the `[output]` table, for a split that
+    /// encodes EPCs when `epc` is set and groups stores by `grouping`.
+    pub fn output_layout(&self, epc: bool, grouping: Grouping) -> Result<OutputLayout> {
+        OutputLayout::new(&self.output, epc, grouping).context("Invalid `[output]` config")
+    }
+
+    /// Returns the encoder of the EPCs, set up by the `[epc]` table.
+    pub fn epc_encoder(&self) -> Result<Sgtin96> {
+        Sgtin96::new(&self.epc).context("Invalid `[epc]` config")
+    }
+
+    /// Reads the store directory, or returns `None` if none is configured.
+    pub fn store_directory(&self) -> Result<Option<StoreDirectory>> {
+        self.directory
+            .path
+            .as_deref()
+            .map(StoreDirectory::load)
+            .transpose()
+    }
+
+    /// Returns the encoder of the SSCCs of cartons, set up by the `[sscc]` table.
+    pub fn sscc_encoder(&self) -> Result<Sscc> {
+        Sscc::new(&self.sscc).context("Invalid `[sscc]` config")
+    }
+
+    /// Reads the config file at `path`, or [DEFAULT_CONFIG] if it exists when no path is given.
+    /// Without either, the default config is used.
+    pub fn load_or_default(path: Option<PathBuf>) -> Result<Config> {
+        match path {
+            Some(path) => Config::load(&path),
+            None if Path::new(DEFAULT_CONFIG).exists() => Config::load(Path::new(DEFAULT_CONFIG)),
+            None => Ok(Config::default()),
+        }
+    }
+}
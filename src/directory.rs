@@ -0,0 +1,241 @@
+use crate::columns::normalize;
+use crate::location::Location;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Settings of the store directory, e.g.
+///
+/// ```toml
+/// [directory]
+/// path = "//fileserver/labels/stores.csv"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectoryConfig {
+    /// The store directory, see [StoreDirectory]. Stores are only known by number without it.
+    pub path: Option<PathBuf>,
+}
+
+/// A store of the store directory and where its orders ship to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreInfo {
+    /// The three digit store number, e.g. `001`.
+    pub number: String,
+    pub name: String,
+    pub address: String,
+    pub city: String,
+    pub state: String,
+    pub zip: String,
+    pub region: String,
+    /// The distribution center the store's orders ship through.
+    pub dc: String,
+}
+
+impl StoreInfo {
+    /// Returns the ship-to address of the store, without its empty lines, e.g.
+    /// `["Downtown", "12 Main St", "Springfield, IL 62701"]`.
+    pub fn ship_to(&self) -> Vec<String> {
+        let place = format!("{} {}", self.state, self.zip);
+        let last_line = match (self.city.is_empty(), place.trim()) {
+            (false, "") => self.city.clone(),
+            (false, place) => format!("{}, {}", self.city, place),
+            (true, place) => place.to_owned(),
+        };
+
+        [self.name.clone(), self.address.clone(), last_line]
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+}
+
+/// The columns of the store directory and the header names accepted for each.
+const COLUMNS: [(&str, &[&str]); 8] = [
+    ("store", &["store", "storenumber", "storeno", "number"]),
+    ("name", &["name", "storename"]),
+    ("address", &["address", "street"]),
+    ("city", &["city"]),
+    ("state", &["state", "province"]),
+    ("zip", &["zip", "zipcode", "postalcode"]),
+    ("region", &["region"]),
+    ("dc", &["dc", "distributioncenter"]),
+];
+
+/// Every store the company ships to, read from a CSV file with a row per store, e.g.
+///
+/// ```text
+/// Store,Name,Address,City,State,Zip,Region,DC
+/// 001,Downtown,12 Main St,Springfield,IL,62701,North,DC1
+/// ```
+///
+/// Only the `Store` column is required. Headers are compared ignoring case, spaces and
+/// punctuation, and store numbers that lost their leading zeros in a spreadsheet, e.g. `1`, are
+/// read as `001`.
+#[derive(Debug, Clone, Default)]
+pub struct StoreDirectory {
+    stores: BTreeMap<String, StoreInfo>,
+}
+
+impl StoreDirectory {
+    /// Reads the store directory at `path`.
+    pub fn load(path: &Path) -> Result<StoreDirectory> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the store directory {}", path.display()))?;
+
+        StoreDirectory::parse(&text)
+            .with_context(|| format!("Invalid store directory {}", path.display()))
+    }
+
+    /// Parses the CSV text of a store directory.
+    pub fn parse(text: &str) -> Result<StoreDirectory> {
+        let mut rdr = csv::Reader::from_reader(text.as_bytes());
+        let headers: Vec<String> = rdr.headers()?.iter().map(normalize).collect();
+        let index = |column: &str| {
+            COLUMNS
+                .iter()
+                .find(|(name, _)| *name == column)
+                .and_then(|(_, aliases)| {
+                    headers
+                        .iter()
+                        .position(|header| aliases.contains(&header.as_str()))
+                })
+        };
+        let store_index = index("store").ok_or_else(|| anyhow!("There is no `Store` column"))?;
+
+        let mut stores = BTreeMap::new();
+        for record in rdr.records() {
+            let record = record?;
+            let line = record.position().map_or(0, |position| position.line());
+            let value = |column: &str| {
+                index(column)
+                    .and_then(|index| record.get(index))
+                    .unwrap_or_default()
+                    .trim()
+                    .to_owned()
+            };
+
+            let number = record.get(store_index).unwrap_or_default().trim();
+            if number.is_empty() {
+                continue;
+            }
+            let number =
+                store_number(number).context(Location::new(line, Some(store_index + 1)))?;
+            let store = StoreInfo {
+                number: number.clone(),
+                name: value("name"),
+                address: value("address"),
+                city: value("city"),
+                state: value("state"),
+                zip: value("zip"),
+                region: value("region"),
+                dc: value("dc"),
+            };
+            if stores.insert(number.clone(), store).is_some() {
+                return Err(anyhow!("The store {} is listed more than once", number))
+                    .context(Location::new(line, Some(store_index + 1)));
+            }
+        }
+
+        Ok(StoreDirectory { stores })
+    }
+
+    /// Returns the store numbered `number`, if it is in the directory.
+    pub fn get(&self, number: &str) -> Option<&StoreInfo> {
+        self.stores.get(number)
+    }
+
+    /// Returns the numbers of `numbers` that aren't in the directory, in order and without
+    /// duplicates.
+    pub fn unknown<'a>(&self, numbers: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut unknown: Vec<String> = vec![];
+        for number in numbers {
+            if !self.stores.contains_key(number) && !unknown.iter().any(|n| n == number) {
+                unknown.push(number.to_owned());
+            }
+        }
+        unknown
+    }
+
+    /// Fails if any store of the store list, given by its `numbers`, isn't in the directory.
+    pub fn check_list(&self, numbers: &[String]) -> Result<()> {
+        let unknown = self.unknown(numbers.iter().map(|number| number.as_str()));
+        if !unknown.is_empty() {
+            bail!(
+                "The store list has {} store(s) that are not in the store directory: {}",
+                unknown.len(),
+                unknown.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Returns `number` as a three digit store number, padding it with zeros.
+fn store_number(number: &str) -> Result<String> {
+    if number.is_empty() || number.len() > 3 || !number.chars().all(|c| c.is_ascii_digit()) {
+        bail!("`{}` is not a three digit store number", number);
+    }
+    Ok(format!("{:0>3}", number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTORY: &str = "\
+Store No.,Store Name,Street,City,State,Zip Code,Region,Distribution Center
+1,Downtown,12 Main St,Springfield,IL,62701,North,DC1
+045,Mall,,Shelbyville,,,South,
+";
+
+    #[test]
+    fn pads_store_numbers_that_lost_their_zeros() {
+        let directory = StoreDirectory::parse(DIRECTORY).unwrap();
+        let store = directory.get("001").unwrap();
+        assert_eq!(store.number, "001");
+        assert_eq!(store.dc, "DC1");
+        assert!(directory.get("1").is_none());
+        assert_eq!(directory.get("045").unwrap().region, "South");
+    }
+
+    #[test]
+    fn leaves_empty_lines_out_of_the_ship_to() {
+        let directory = StoreDirectory::parse(DIRECTORY).unwrap();
+        assert_eq!(
+            directory.get("001").unwrap().ship_to(),
+            ["Downtown", "12 Main St", "Springfield, IL 62701"]
+        );
+        assert_eq!(
+            directory.get("045").unwrap().ship_to(),
+            ["Mall", "Shelbyville"]
+        );
+    }
+
+    #[test]
+    fn lists_unknown_stores() {
+        let directory = StoreDirectory::parse(DIRECTORY).unwrap();
+        assert_eq!(
+            directory.unknown(["001", "002", "002", "100"]),
+            ["002", "100"]
+        );
+        let error = directory
+            .check_list(&["045".to_owned(), "002".to_owned()])
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("1 store(s) that are not in the store directory: 002"));
+    }
+
+    #[test]
+    fn locates_bad_and_repeated_stores() {
+        let error = StoreDirectory::parse("Name,Store\nA,1\nB,001\n").unwrap_err();
+        assert_eq!(Location::of(&error), Some(Location::new(3, Some(2))));
+        assert!(format!("{:#}", error).contains("The store 001 is listed more than once"));
+
+        let error = StoreDirectory::parse("Store\n1000\n").unwrap_err();
+        assert_eq!(Location::of(&error), Some(Location::new(2, Some(1))));
+        assert!(StoreDirectory::parse("Name\nA\n").is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// The largest serial number an SGTIN-96 can hold, 38 bits.
+pub const MAX_SERIAL: u64 = (1 << 38) - 1;
+
+/// The header of an SGTIN-96 EPC.
+const SGTIN_96: u128 = 0x30;
+
+/// The bits and digits of the company prefix for each partition value of an SGTIN-96, from the
+/// GS1 Tag Data Standard. The item reference gets the remaining 44 bits and 13 digits.
+const PARTITIONS: [(u32, usize); 7] = [
+    (40, 12),
+    (37, 11),
+    (34, 10),
+    (30, 9),
+    (27, 8),
+    (24, 7),
+    (20, 6),
+];
+
+/// Settings of the EPCs encoded into the RFID tags, e.g.
+///
+/// ```toml
+/// [epc]
+/// filter = 1
+///
+/// [[epc.company_prefix]]
+/// prefix = "0614141"
+///
+/// [[epc.company_prefix]]
+/// prefix = "08"
+/// length = 9
+/// ```
+///
+/// Serial numbers are handed out from the `serials` file, which can be shared by every machine
+/// printing labels, see [crate::serials::SerialAllocator].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EpcConfig {
+    /// The filter value of every EPC, `1` for a point of sale item.
+    pub filter: u8,
+    /// The GS1 company prefixes of the UPCs ordered, used to split a GTIN into its company
+    /// prefix and item reference.
+    #[serde(rename = "company_prefix")]
+    pub company_prefixes: Vec<CompanyPrefix>,
+    /// The file keeping track of the serial numbers used, `lisa_serials.json` when left out.
+    pub serials: PathBuf,
+}
+
+impl Default for EpcConfig {
+    fn default() -> Self {
+        EpcConfig {
+            filter: 1,
+            company_prefixes: vec![],
+            serials: PathBuf::from("lisa_serials.json"),
+        }
+    }
+}
+
+/// A GS1 company prefix, or the first digits of a range of them.
+///
+/// Prefixes are written as in a GTIN-13, so the prefix of a UPC starts with a `0`. The prefix of
+/// a GTIN is the longest `prefix` it starts with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompanyPrefix {
+    pub prefix: String,
+    /// The number of digits of the company prefixes starting with `prefix`, the length of
+    /// `prefix` itself when left out.
+    pub length: Option<usize>,
+}
+
+/// Returns the GS1 check digit of `digits`, the digits of a GTIN or SSCC without its check digit.
+pub fn check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { digit * 3 } else { digit })
+        .sum();
+
+    (10 - sum % 10) % 10
+}
+
+/// Returns `code`, a GTIN-8, UPC (GTIN-12), EAN-13 or GTIN-14, as a GTIN-14. Fails if it isn't
+/// one or its check digit is wrong.
+pub fn gtin14(code: &str) -> Result<String> {
+    let code = code.trim();
+    if !code.chars().all(|c| c.is_ascii_digit()) || ![8, 12, 13, 14].contains(&code.len()) {
+        bail!("`{}` is not a UPC or GTIN", code);
+    }
+
+    let gtin = format!("{:0>14}", code);
+    let (digits, check) = gtin.split_at(13);
+    if check_digit(digits).to_string() != check {
+        bail!(
+            "The check digit of the UPC `{}` is wrong, expected {}",
+            code,
+            check_digit(digits)
+        );
+    }
+    Ok(gtin)
+}
+
+/// Turns UPCs into SGTIN-96 EPCs.
+#[derive(Debug, Clone)]
+pub struct Sgtin96 {
+    filter: u8,
+    /// The configured prefixes with the length of the company prefixes they start.
+    prefixes: Vec<(String, usize)>,
+}
+
+impl Sgtin96 {
+    /// Checks the filter value and company prefixes of `config`.
+    pub fn new(config: &EpcConfig) -> Result<Sgtin96> {
+        if config.filter > 7 {
+            bail!(
+                "The EPC filter value must be from 0 to 7, not {}",
+                config.filter
+            );
+        }
+
+        let mut prefixes = vec![];
+        for company_prefix in &config.company_prefixes {
+            let prefix = company_prefix.prefix.trim();
+            let length = company_prefix.length.unwrap_or(prefix.len());
+            if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_digit()) {
+                bail!("The company prefix `{}` must be digits", prefix);
+            }
+            if !(6..=12).contains(&length) || prefix.len() > length {
+                bail!(
+                    "The company prefix `{}` must be from 6 to 12 digits long, not {}",
+                    prefix,
+                    length
+                );
+            }
+            prefixes.push((prefix.to_owned(), length));
+        }
+
+        Ok(Sgtin96 {
+            filter: config.filter,
+            prefixes,
+        })
+    }
+
+    /// Returns the length of the company prefix of `gtin`, a GTIN-14.
+    fn company_prefix_len(&self, gtin: &str) -> Result<usize> {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| gtin[1..].starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, length)| *length)
+            .with_context(|| {
+                format!(
+                    "No company prefix of the `[epc]` config matches the GTIN {}",
+                    gtin
+                )
+            })
+    }
+
+    /// Returns the EPC of the item with the UPC or GTIN `code` and the given `serial` number, as
+    /// 24 hex digits.
+    pub fn encode(&self, code: &str, serial: u64) -> Result<String> {
+        let gtin = gtin14(code)?;
+        if serial > MAX_SERIAL {
+            bail!(
+                "The serial number {} is too large for an SGTIN-96, the largest is {}",
+                serial,
+                MAX_SERIAL
+            );
+        }
+
+        let length = self.company_prefix_len(&gtin)?;
+        let partition = PARTITIONS
+            .iter()
+            .position(|(_, digits)| *digits == length)
+            .context("The company prefix length has no partition")?;
+        let (prefix_bits, _) = PARTITIONS[partition];
+
+        // The indicator digit leads the item reference, and the check digit is left out.
+        let company_prefix: u128 = gtin[1..1 + length].parse()?;
+        let item_reference: u128 = format!("{}{}", &gtin[..1], &gtin[1 + length..13]).parse()?;
+
+        let epc = SGTIN_96 << 88
+            | (self.filter as u128) << 85
+            | (partition as u128) << 82
+            | company_prefix << (82 - prefix_bits)
+            | item_reference << 38
+            | serial as u128;
+
+        Ok(format!("{:024X}", epc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(filter: u8, prefixes: &[(&str, Option<usize>)]) -> Sgtin96 {
+        Sgtin96::new(&EpcConfig {
+            filter,
+            company_prefixes: prefixes
+                .iter()
+                .map(|(prefix, length)| CompanyPrefix {
+                    prefix: prefix.to_string(),
+                    length: *length,
+                })
+                .collect(),
+            ..EpcConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn encodes_tag_data_standard_example() {
+        // urn:epc:id:sgtin:0614141.812345.6789 with filter 3, from the GS1 Tag Data Standard.
+        let epc = encoder(3, &[("0614141", None)]).encode("80614141123458", 6789);
+        assert_eq!(epc.unwrap(), "3074257BF7194E4000001A85");
+    }
+
+    #[test]
+    fn encodes_six_digit_company_prefix() {
+        // Partition 6, a 6 digit company prefix and a 7 digit item reference.
+        let epc = encoder(1, &[("061414", None)]).encode("614141234561", 1);
+        assert_eq!(epc.unwrap(), "30383BF98078900000000001");
+    }
+
+    #[test]
+    fn encodes_twelve_digit_company_prefix() {
+        // Partition 0, the item reference is only the indicator digit.
+        let epc = encoder(1, &[("0614141", Some(12))]).encode("614141000012", 42);
+        assert_eq!(epc.unwrap(), "30203932433084000000002A");
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let epc = encoder(3, &[("06", Some(12)), ("0614141", None)]).encode("80614141123458", 6789);
+        assert_eq!(epc.unwrap(), "3074257BF7194E4000001A85");
+    }
+
+    #[test]
+    fn rejects_serial_too_large() {
+        let encoder = encoder(1, &[("0614141", None)]);
+        assert!(encoder.encode("80614141123458", MAX_SERIAL).is_ok());
+        assert!(encoder.encode("80614141123458", MAX_SERIAL + 1).is_err());
+    }
+
+    #[test]
+    fn gtin14_pads_upcs() {
+        assert_eq!(gtin14("614141234561").unwrap(), "00614141234561");
+        assert_eq!(gtin14(" 80614141123458 ").unwrap(), "80614141123458");
+    }
+
+    #[test]
+    fn gtin14_rejects_bad_check_digit() {
+        assert!(gtin14("614141234562").is_err());
+        assert!(gtin14("80614141123457").is_err());
+    }
+
+    #[test]
+    fn gtin14_rejects_wrong_length() {
+        assert!(gtin14("61414123456").is_err());
+        assert!(gtin14("806141411234580").is_err());
+        assert!(gtin14("61414123456A").is_err());
+        assert!(gtin14("").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::directory::StoreDirectory;
+use crate::po::PoNumber;
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+/// The name of the group of stores without a DC or region in the store directory.
+const UNASSIGNED: &str = "Unassigned";
+
+/// How the stores of a split are grouped into files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ArgEnum)]
+pub enum Grouping {
+    /// A file for each store.
+    #[default]
+    Store,
+    /// A file for each distribution center of the store directory.
+    Dc,
+    /// A file for each region of the store directory.
+    Region,
+    /// A single file for every store.
+    Combined,
+}
+
+impl Grouping {
+    pub const ALL: [Grouping; 4] = [
+        Grouping::Store,
+        Grouping::Dc,
+        Grouping::Region,
+        Grouping::Combined,
+    ];
+
+    /// Returns the name of the grouping shown in GUI mode.
+    pub fn label(&self) -> &str {
+        match self {
+            Grouping::Store => "A file per store",
+            Grouping::Dc => "A file per DC",
+            Grouping::Region => "A file per region",
+            Grouping::Combined => "One combined file",
+        }
+    }
+}
+
+/// Stores whose lines are written to the same file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// The name of the files of the group, without their extension.
+    pub stem: String,
+    /// The POs of the stores of the group, sorted by store number.
+    pub stores: Vec<PoNumber>,
+}
+
+/// Groups the stores of `pos` by `grouping`, each group keeping to the stores of one base PO.
+///
+/// Grouping by DC or region looks the stores up in `directory`, and stores without one are
+/// grouped as `Unassigned`. Fails if there is no directory to look them up in.
+pub fn groups(
+    pos: impl IntoIterator<Item = PoNumber>,
+    grouping: Grouping,
+    directory: Option<&StoreDirectory>,
+) -> Result<Vec<Group>> {
+    let directory = match (grouping, directory) {
+        (Grouping::Dc | Grouping::Region, None) => {
+            bail!("Grouping the files by DC or region needs a store directory in the config")
+        }
+        (_, directory) => directory,
+    };
+
+    let mut groups: BTreeMap<String, Vec<PoNumber>> = BTreeMap::new();
+    for po in pos {
+        let info = directory.and_then(|directory| directory.get(po.store()));
+        let name = |value: Option<&String>| {
+            let value = value.map_or("", |value| value.as_str());
+            match value.is_empty() {
+                true => UNASSIGNED.to_owned(),
+                false => value.to_owned(),
+            }
+        };
+
+        let stem = match grouping {
+            Grouping::Store => file_stem(&po, directory),
+            Grouping::Dc => format!(
+                "{}_DC_{}",
+                po.base(),
+                words(&name(info.map(|info| &info.dc)))
+            ),
+            Grouping::Region => format!(
+                "{}_Region_{}",
+                po.base(),
+                words(&name(info.map(|info| &info.region)))
+            ),
+            Grouping::Combined => po.base().to_owned(),
+        };
+        groups.entry(stem).or_default().push(po);
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(stem, mut stores)| {
+            stores.sort_by(|a, b| a.store().cmp(b.store()).then_with(|| a.cmp(b)));
+            stores.dedup();
+            Group { stem, stores }
+        })
+        .collect())
+}
+
+/// Returns the name of the files written for the store of `po`, without their extension. Stores
+/// in `directory` get their name and city added, e.g. `po14423-001_Downtown_Springfield`.
+pub fn file_stem(po: &PoNumber, directory: Option<&StoreDirectory>) -> String {
+    let mut stem = po.to_string();
+    let Some(info) = directory.and_then(|directory| directory.get(po.store())) else {
+        return stem;
+    };
+
+    for part in [&info.name, &info.city] {
+        let part = words(part);
+        if !part.is_empty() {
+            stem.push('_');
+            stem.push_str(&part);
+        }
+    }
+    stem
+}
+
+/// Returns the words of `text` joined by dashes. Only letters and digits are kept, so the text is
+/// safe in a file name on any file system.
+fn words(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::po::PoPattern;
+
+    fn pos(pos: &[&str]) -> Vec<PoNumber> {
+        let pattern = PoPattern::from_delimiter("-");
+        pos.iter().map(|po| pattern.parse(po).unwrap()).collect()
+    }
+
+    fn directory() -> StoreDirectory {
+        StoreDirectory::parse(
+            "Store,Name,City,Region,DC\n\
+             001,Downtown,Springfield,North,Northeast DC\n\
+             002,Mall,Shelbyville,North,\n\
+             003,Outlet,Ogdenville,,Northeast DC\n",
+        )
+        .unwrap()
+    }
+
+    fn stems(groups: &[Group]) -> Vec<(&str, Vec<String>)> {
+        groups
+            .iter()
+            .map(|group| {
+                let stores = group.stores.iter().map(|po| po.to_string()).collect();
+                (group.stem.as_str(), stores)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_by_dc_with_unassigned_stores() {
+        let directory = directory();
+        let groups = groups(
+            pos(&["po1-003", "po1-001", "po1-002", "po1-004", "po1-001"]),
+            Grouping::Dc,
+            Some(&directory),
+        )
+        .unwrap();
+        assert_eq!(
+            stems(&groups),
+            [
+                (
+                    "po1_DC_Northeast-DC",
+                    vec!["po1-001".into(), "po1-003".into()]
+                ),
+                (
+                    "po1_DC_Unassigned",
+                    vec!["po1-002".into(), "po1-004".into()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_by_region_within_each_order() {
+        let directory = directory();
+        let groups = groups(
+            pos(&["po1-001", "po2-002", "po1-003"]),
+            Grouping::Region,
+            Some(&directory),
+        )
+        .unwrap();
+        assert_eq!(
+            stems(&groups),
+            [
+                ("po1_Region_North", vec!["po1-001".into()]),
+                ("po1_Region_Unassigned", vec!["po1-003".into()]),
+                ("po2_Region_North", vec!["po2-002".into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_store_files_after_the_store() {
+        let directory = directory();
+        let stores = groups(
+            pos(&["po1-001", "po1-009"]),
+            Grouping::Store,
+            Some(&directory),
+        );
+        assert_eq!(
+            stems(&stores.unwrap()),
+            [
+                ("po1-001_Downtown_Springfield", vec!["po1-001".into()]),
+                ("po1-009", vec!["po1-009".into()]),
+            ]
+        );
+        let combined = groups(pos(&["po1-002", "po1-001"]), Grouping::Combined, None).unwrap();
+        assert_eq!(
+            stems(&combined),
+            [("po1", vec!["po1-001".into(), "po1-002".into()])]
+        );
+    }
+
+    #[test]
+    fn needs_a_directory_to_group_by_dc() {
+        assert!(groups(pos(&["po1-001"]), Grouping::Dc, None).is_err());
+    }
+}
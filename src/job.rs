@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// How far a job has got, as last reported by the job.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressState {
+    pub rows_read: u64,
+    /// The bytes of the PO file read so far, out of `bytes_total`.
+    pub bytes_read: u64,
+    pub bytes_total: u64,
+    pub stores_written: usize,
+    pub stores_total: usize,
+    /// The file being written.
+    pub current_file: Option<PathBuf>,
+}
+
+impl ProgressState {
+    /// Returns how much of the job is done, from `0.0` to `1.0`.
+    ///
+    /// While the PO file is read this is how much of the file has been read, then how many of
+    /// the stores have been written.
+    pub fn fraction(&self) -> f32 {
+        if self.stores_total > 0 {
+            self.stores_written as f32 / self.stores_total as f32
+        } else if self.bytes_total > 0 {
+            self.bytes_read as f32 / self.bytes_total as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for ProgressState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.current_file {
+            Some(file) => write!(
+                f,
+                "Writing {} ({} of {} stores written)",
+                file.display(),
+                self.stores_written,
+                self.stores_total
+            ),
+            None => write!(f, "Read {} rows", self.rows_read),
+        }
+    }
+}
+
+/// The error returned by a job that was cancelled.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The job was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Shared between a job and whoever is watching it, so the job can report its progress and be
+/// asked to stop.
+///
+/// Jobs run from the command line are given a `Progress` nobody watches or cancels.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    state: Arc<Mutex<ProgressState>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// Updates the progress of the job.
+    pub fn update(&self, update: impl FnOnce(&mut ProgressState)) {
+        if let Ok(mut state) = self.state.lock() {
+            update(&mut state);
+        }
+    }
+
+    /// Returns a copy of the progress of the job.
+    pub fn state(&self) -> ProgressState {
+        self.state
+            .lock()
+            .map(|state| state.clone())
+            .unwrap_or_default()
+    }
+
+    /// Asks the job to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [Cancelled] if the job has been asked to stop. Jobs call this between units of
+    /// work, so they stop at a point where they can clean up after themselves.
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(Cancelled.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A job running on a worker thread, so the GUI stays responsive while it runs.
+#[derive(Debug)]
+pub struct Job<T> {
+    progress: Progress,
+    handle: JoinHandle<Result<T>>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    /// Runs `job` on a new thread.
+    pub fn spawn(job: impl FnOnce(&Progress) -> Result<T> + Send + 'static) -> Job<T> {
+        let progress = Progress::default();
+        let handle = {
+            let progress = progress.clone();
+            std::thread::spawn(move || job(&progress))
+        };
+
+        Job { progress, handle }
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the job to finish and returns its result.
+    pub fn join(self) -> Result<T> {
+        self.handle
+            .join()
+            .map_err(|_| anyhow!("The job stopped unexpectedly"))?
+    }
+}
@@ -31,7 +31,7 @@ pub mod message_box {
         pub fn show(&self) {
             MessageDialog::new()
                 .set_title("Success")
-                .set_description(&self.value())
+                .set_description(self.value())
                 .set_level(MessageLevel::Info)
                 .set_buttons(MessageButtons::Ok)
                 .show();
@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// Where in an input file a problem was found.
+///
+/// Errors about a particular line of a PO file or store list carry a `Location` as context, so
+/// the GUI can point the user at the line to fix, e.g. `line 12, column 3: ...`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// The 1-based line of the file.
+    pub line: u64,
+    /// The 1-based column, or field of a CSV file, when known.
+    pub column: Option<usize>,
+}
+
+impl Location {
+    pub fn new(line: u64, column: Option<usize>) -> Location {
+        Location { line, column }
+    }
+
+    /// Returns the location attached to `error`, if any.
+    pub fn of(error: &anyhow::Error) -> Option<Location> {
+        error.downcast_ref::<Location>().copied()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}, column {}", self.line, column),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
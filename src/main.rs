@@ -1,72 +1,246 @@
-use anyhow::{Context, Ok, Result};
-use clap::Parser;
+use anyhow::{bail, Context, Ok, Result};
+use clap::{Args, Parser, Subcommand};
 use csv::StringRecord;
 use eframe::egui;
-use egui::{global_dark_light_mode_buttons, global_dark_light_mode_switch};
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use egui::global_dark_light_mode_switch;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::path::PathBuf;
-use std::str::SplitWhitespace;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
 use lisa::message_box::ErrorMsgBox;
+mod carton;
+mod columns;
+mod config;
+mod directory;
+mod epc;
+mod grouping;
+mod job;
+mod location;
+mod output;
+mod packing;
+mod po;
+mod print;
+mod profile;
+mod report;
+mod rfid;
+mod serials;
+mod sscc;
+mod stores;
+mod upc_list;
 mod windows;
-use windows::report;
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct Order {
-    po: String,
-    style_code: String,
-    color_code: String,
-    msrp_size: String,
-    style_desc: String,
-    color_desc: String,
-    upc: String,
-    store_num: String,
-    qty: String,
-}
-
-/// Fields pertaining to the RFID CSV produced by the Infinity app.
-enum Field {
-    Po,
-    Style,
-    ColorCode,
-    Size,
-    StyleDesc,
-    ColorDesc,
-    Upc,
-    Qty,
-}
-
-impl Field {
-    /// Returns the index of a specific field
-    fn get(&self) -> usize {
-        match &self {
-            Field::Po => 0,
-            Field::Style => 1,
-            Field::ColorCode => 2,
-            Field::Size => 3,
-            Field::StyleDesc => 4,
-            Field::ColorDesc => 5,
-            Field::Upc => 6,
-            Field::Qty => 8,
-        }
-    }
-}
-
-/// Returns a StringRecord, which is the type the CSV crate uses to represent a CSV file.
-fn read_file(file_path: PathBuf) -> Result<Vec<StringRecord>> {
-    let file = File::open(file_path).context("Failed to open file")?;
+mod workbook;
+mod xlsx;
+mod zpl;
+use carton::{CartonClass, Cartons};
+use columns::{Column, ColumnMap, Field};
+use config::Config;
+use directory::StoreDirectory;
+use epc::Sgtin96;
+use grouping::Grouping;
+use job::{Cancelled, Job, Progress};
+use location::Location;
+use output::{OutputField, OutputLayout};
+use packing::{PackItem, PackingList};
+use po::{PoNumber, PoPattern};
+use profile::Profile;
+use report::{CartonTotals, PreTaggedLine, Report, ReportFormat, StoreTotals};
+use rfid::{Classification, RfidRules};
+use serials::{Request, Selection, SerialAllocator};
+use sscc::Sscc;
+use stores::{StoreList, StoreSelection};
+use windows::report::ReportWindow;
+use windows::status::Status;
+use workbook::Sheet;
+use xlsx::{FileFormat, SheetWriter};
+use zpl::{Label, ZplTemplate};
+/// A PO file read into memory along with its profile and the position of each of its columns.
+struct PoFile {
+    profile: Profile,
+    columns: ColumnMap,
+    records: Vec<StringRecord>,
+}
+
+/// The extensions of the PO files that can be picked in GUI mode.
+const PO_EXTENSIONS: [&str; 5] = ["csv", "txt", "xlsx", "xlsm", "xls"];
+
+/// A PO file to read, a CSV file or a sheet of a workbook.
+#[derive(Debug, Clone, Default)]
+struct PoInput {
+    path: PathBuf,
+    /// The sheet of a workbook to read, its first sheet when `None`.
+    sheet: Option<String>,
+    /// The title rows above the header row of a workbook, found by looking for the header when
+    /// `None`.
+    skip_rows: Option<usize>,
+}
+
+/// Reads a PO file, finding its columns by the names in its header row.
+///
+/// The profile called `profile` is used if given, otherwise it is detected from the header row,
+/// see [profile::select]. Fails if any of the columns LISA needs is missing from the header, or
+/// with the [Location] of the first line that can't be read.
+fn read_file(
+    input: &PoInput,
+    config: &Config,
+    profile: Option<&str>,
+    progress: &Progress,
+) -> Result<PoFile> {
+    if workbook::is_workbook(&input.path) {
+        return read_workbook(input, config, profile, progress);
+    }
+
+    let file_path = &input.path;
+    let file = File::open(file_path)
+        .with_context(|| format!("Failed to open file {}", file_path.display()))?;
+    let bytes_total = file.metadata().map_or(0, |metadata| metadata.len());
+    progress.update(|state| state.bytes_total = bytes_total);
     let mut rdr = csv::Reader::from_reader(file);
+    let (profile, columns) = profile::select(rdr.headers()?, config, profile)?;
     let mut records: Vec<StringRecord> = vec![];
 
     for result in rdr.records() {
-        records.push(result?);
+        progress.check()?;
+        match result {
+            Result::Ok(record) => {
+                let bytes_read = record.position().map_or(0, |position| position.byte());
+                progress.update(|state| {
+                    state.rows_read += 1;
+                    state.bytes_read = bytes_read;
+                });
+                records.push(record);
+            }
+            Err(e) => {
+                let location = e
+                    .position()
+                    .map(|position| Location::new(position.line(), None));
+                return match location {
+                    Some(location) => Err(anyhow::Error::new(e).context(location)),
+                    None => Err(e.into()),
+                };
+            }
+        }
     }
 
-    Ok(records)
+    Ok(PoFile {
+        profile,
+        columns,
+        records,
+    })
+}
+
+/// Reads a PO file from a sheet of a workbook, with each row read as a line of a CSV file.
+///
+/// The header row is the row below the `skip_rows` of `input`, or else the first of the top rows
+/// of the sheet that fits a profile, so title rows above the header are skipped.
+fn read_workbook(
+    input: &PoInput,
+    config: &Config,
+    profile: Option<&str>,
+    progress: &Progress,
+) -> Result<PoFile> {
+    let sheet = Sheet::open(&input.path, input.sheet.as_deref())?;
+    progress.check()?;
+
+    let header = match input.skip_rows {
+        Some(skip_rows) => skip_rows,
+        None => sheet.find_header(|headers| profile::select(headers, config, profile).is_ok()),
+    };
+    let (profile, columns) = profile::select(&sheet.record(header, None), config, profile)
+        .context(Location::new(header as u64 + 1, None))?;
+    let records = sheet.records(header, columns.index(&Column::Field(Field::Upc)));
+    progress.update(|state| state.rows_read = records.len() as u64);
+
+    Ok(PoFile {
+        profile,
+        columns,
+        records,
+    })
+}
+
+/// Returns the location of `field` of `record` within the PO file.
+fn location(record: &StringRecord, columns: &ColumnMap, field: Field) -> Location {
+    // Records read from a file always have a position.
+    let line = record.position().map_or(0, |position| position.line());
+    Location::new(line, columns.column(field))
+}
+
+/// A record of a PO file along with its parsed PO number.
+#[derive(Debug)]
+struct Line {
+    po: PoNumber,
+    record: StringRecord,
+    /// The quantity ordered.
+    qty: u32,
+    /// Whether the item already has an RFID tag, see [has_rfid].
+    rfid: Classification,
+    /// The EPC of each label to print, empty unless EPCs are encoded, see [assign_epcs].
+    epcs: Vec<String>,
+}
+
+/// The kinds of file a split writes for each store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FileKind {
+    /// The CSV or XLSX file of the lines of the store's PO, or the sheet of the workbook with
+    /// this name.
+    Order(Option<String>),
+    /// The ZPL labels of the store.
+    Labels,
+    /// The packing list of the store, as CSV or as a printable summary.
+    PackingList,
+    /// The ZPL ship labels of the cartons of the store.
+    ShipLabels,
+}
+
+/// What is written for each store when a PO is split.
+#[derive(Debug, Clone, Default)]
+struct Outputs {
+    /// Print labels for pre-tagged items too.
+    print_all: bool,
+    /// The template of the ZPL labels written next to each CSV file, or `None` to write none.
+    zpl: Option<ZplTemplate>,
+    /// The encoder of the EPC of each label, or `None` to encode none.
+    epc: Option<Sgtin96>,
+    /// Write the packing list of each store, see [PackingList].
+    packing: bool,
+    /// The encoder of the SSCC of each carton, printed on a ship label for each, or `None` to
+    /// write no ship labels.
+    sscc: Option<Sscc>,
+    /// How the stores are grouped into files.
+    grouping: Grouping,
+    /// The format of the files of each group.
+    format: FileFormat,
+    /// The columns of the files.
+    layout: OutputLayout,
+}
+
+/// The quantity of a line split into the labels to print and the items that need none.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Quantities {
+    labels_to_print: u32,
+    pre_tagged: u32,
+}
+
+impl Line {
+    /// Classifies the quantity of the line. Pre-tagged items need no label, unless `print_all`
+    /// is set.
+    ///
+    /// Splitting a PO and reporting on it both count labels with this, so the report always
+    /// describes the files that are written.
+    fn quantities(&self, print_all: bool) -> Quantities {
+        match self.rfid.pre_tagged && !print_all {
+            true => Quantities {
+                labels_to_print: 0,
+                pre_tagged: self.qty,
+            },
+            false => Quantities {
+                labels_to_print: self.qty,
+                pre_tagged: 0,
+            },
+        }
+    }
 }
 
 /// filter_store() returns a vector of items that are found in `list: Vec<String>`.
@@ -77,302 +251,925 @@ fn read_file(file_path: PathBuf) -> Result<Vec<StringRecord>> {
 /// are interested in and returns only the POs of the sores found in the list.
 ///
 /// The `list` is made by the end user. It is a text file that lists the store numbers
-/// to be returned.
-fn filter_store(records: Vec<StringRecord>, list: Vec<String>) -> Result<Vec<StringRecord>> {
+/// to be returned. The PO of each record is parsed with the `pattern` of the PO file's profile,
+/// and a record is returned when its store number is exactly one in the list, so each record is
+/// returned at most once, in the order of the PO file. Records whose PO doesn't match the
+/// pattern, such as a totals row, are skipped with a warning.
+///
+/// When every store is selected instead of a list, the stores are discovered from the POs of
+/// the file itself and only the excluded stores are left out. A PO that doesn't match the
+/// pattern then fails the run, as its store can't be known to be excluded.
+///
+/// Each returned record is classified with the RFID `rules` of the profile, see [has_rfid].
+fn filter_store(
+    records: Vec<StringRecord>,
+    columns: &ColumnMap,
+    pattern: &PoPattern,
+    stores: &StoreSelection,
+    rules: &RfidRules,
+) -> Result<Vec<Line>> {
     let mut filtered_records = vec![];
 
-    for num in list {
-        let num = format!("-{}", &num);
-        for item in records.clone().into_iter() {
-            if item
-                .get(Field::Po.get())
-                .expect("Was unable to get the PO")
-                .to_owned()
-                .contains(&num)
-            {
-                filtered_records.push(item)
+    for item in records {
+        let po = match pattern.parse(columns.get(&item, Field::Po)) {
+            Result::Ok(po) => po,
+            Err(e) if matches!(stores, StoreSelection::List(_)) => {
+                warn!("Skipped {}: {}", location(&item, columns, Field::Po), e);
+                continue;
             }
+            Err(e) => return Err(e.context(location(&item, columns, Field::Po))),
+        };
+
+        if stores.includes(po.store()) {
+            let qty = qty(&item, columns)?;
+            let rfid = has_rfid(&item, columns, rules);
+            filtered_records.push(Line {
+                po,
+                record: item,
+                qty,
+                rfid,
+                epcs: vec![],
+            })
         }
     }
 
+    if let StoreSelection::All { exclude } = stores {
+        let found = filtered_records
+            .iter()
+            .map(|line| line.po.store())
+            .collect::<HashSet<&str>>();
+        info!(
+            "Processing all {} store(s) found in the PO file, excluding {:?}",
+            found.len(),
+            exclude
+        );
+    }
+
     Ok(filtered_records)
 }
 
-/// has_rfid() returns a `true` if an item is _thought_ to have a RFID tag already applied
+/// has_rfid() decides if an item is _thought_ to have a RFID tag already applied
 /// from the factory.
 ///
 /// Some items already have an RFID tag applied, or will have one in the near future. Items
 /// that may have said RFID will have a `$` charter at the end of the  item name description.
+/// Other retailers mark them differently, so the decision is made by the first of the
+/// [RfidRules] of the PO file's profile that fires. The rule is returned with the decision so
+/// it can be explained.
 ///
 /// The reason we care to know this information within the context of this application is because
 /// if an item already has an RFID tag, we do not need to print an RFID tag. This function dictates
 /// weather the qty is left as is or set to `0`.
-fn has_rfid(record: &StringRecord) -> bool {
-    if record
-        .get(Field::StyleDesc.get())
-        .unwrap()
-        .to_string()
-        .contains("$")
-    {
-        return true;
+fn has_rfid(record: &StringRecord, columns: &ColumnMap, rules: &RfidRules) -> Classification {
+    let classification = rules.classify(record);
+
+    if let Some(reason) = &classification.reason {
+        debug!(
+            "UPC {} on {} is {} by the {}",
+            columns.get(record, Field::Upc),
+            location(record, columns, Field::Upc),
+            match classification.pre_tagged {
+                true => "pre-tagged",
+                false => "not pre-tagged",
+            },
+            reason
+        );
     }
-
-    return false;
+    classification
 }
 
-/// list() takes a path to a text file which contains a list of numbers store numbers.
+/// list() takes a path to a file which contains a list of store numbers.
 ///
 /// The csv files received for purchase orders for direct to store includes orders made for a
 /// variety of different stores. Each store is identified by a _store number_.
 ///
-/// This function reads the text file the end user creates which lists all the store numbers
+/// This function reads the file the end user creates which lists all the store numbers
 /// we are interested in. Each store number contains three digits, for example store `1` would
-/// be written as `001. Each of the store numbers _must_ be written using a three digit format
-/// or errors, such as items duplication, will occur. Also, each store number must be separated by
-/// comma, `,`, for the `list` function to work.
+/// be written as `001`. The list is either a TOML file or the original comma separated text
+/// file, see [StoreList]. Store numbers that aren't three digits are reported with the line
+/// and column they were found on.
 ///
-/// TODO: Come up with a better and more robust method to acquire store numbers from the user.
-/// TODO: Perhaps using a format such as TOML.
-/// TODO: Also, write checks and tests to catch user errors when store numbers are added, such as one or two digits for a store number.
-fn list(path: PathBuf) -> Vec<String> {
+/// Returns the numbers of the enabled stores, without duplicates.
+fn list(path: PathBuf) -> Result<Vec<String>> {
     info!("Entering list()");
 
-    let file = std::fs::read_to_string(path)
-        .expect(
-            "[ list() ] Could not read the file containing the stores to search for, check file",
-        )
-        .lines()
-        .collect::<String>();
-
-    let file = file
-        .split(",")
-        .map(|x| x.to_owned())
-        .collect::<Vec<String>>();
+    let store_list = StoreList::load(&path)?;
+    for store in store_list.stores().iter().filter(|store| !store.enabled) {
+        info!("Skipping disabled store {}", store);
+    }
+    let file = store_list.numbers();
 
     debug!("file: {:#?}", &file);
     info!("Exiting list()");
-    file
+    Ok(file)
 }
 
+/// write_file() writes the records of each PO to its own CSV file in `destination_path`, or of
+/// each group of stores when `outputs` groups them, see [grouping::groups]. The files are XLSX
+/// workbooks, or sheets of a workbook for each order, in the [FileFormat] of `outputs`.
+///
+/// Each file is first written with a `.part` extension and only renamed once every file has been
+/// written, so a run that fails or is cancelled through `progress` leaves no half-written files
+/// in the destination, see [PartFiles].
+///
+/// A ZPL file of labels is written next to each file when `outputs` has a label template.
+/// The packing list of each store and the ship labels of its cartons are written from its plan
+/// in `plans` when `outputs` asks for them. The files of a store in the `directory` are named
+/// after it, see [grouping::file_stem].
+///
+/// Returns every file written, with the POs it is for.
 fn write_file(
-    records: Vec<StringRecord>,
+    records: Vec<Line>,
+    columns: &ColumnMap,
     destination_path: PathBuf,
-    print_all: bool,
-) -> Result<()> {
+    plans: &HashMap<PoNumber, PackingList>,
+    directory: Option<&StoreDirectory>,
+    outputs: &Outputs,
+    progress: &Progress,
+) -> Result<Vec<(Vec<PoNumber>, PathBuf, FileKind)>> {
     info!("Entering write_file");
     debug!("`records` parameter: {:#?}", &records);
-    debug!("destination_path: {}", &destination_path.to_str().unwrap());
-    debug!("print_all: {}", &print_all);
+    debug!("destination_path: {}", &destination_path.display());
+    debug!("outputs: {:?}", &outputs);
 
-    // Create a list of stores.
-    //
-    // By using a HashSet, we remove all duplicated records from the vector.
-    // We acquire a set of unique POs that we can use as file names below.
-    let store_list = records
+    // The stores of each file to write, with the lines of each store in the order of the PO file.
+    let stores = records
         .iter()
-        .map(|num| num.get(0).unwrap().to_owned())
-        .collect::<HashSet<String>>();
+        .map(|line| line.po.clone())
+        .collect::<HashSet<PoNumber>>();
+    let groups = grouping::groups(stores, outputs.grouping, directory)?;
+    progress
+        .update(|state| state.stores_total = groups.iter().map(|group| group.stores.len()).sum());
+    let lines_of = |store: &PoNumber| {
+        let store = store.clone();
+        records.iter().filter(move |line| line.po == store)
+    };
+    outputs.layout.check_columns(columns)?;
 
     let file_path = destination_path;
-
-    // This outer loop creates a file and iterates through `store_list` to find the POs for said file.
-    //
-    // For example, if we have a store_list of the following POs: ["po14423-001", "po14423-002", "po14423-003"]
-    // then this outer loop will step through the list and pull out each po one at a time and do the following:
-    //      1) Set the po as a file name
-    //      2) Create a find all matching POs in store_list and use it with `wtr.serialize()`
-    //      3) Push it to a file
-    for store in store_list {
-        let file_name = file_path.join(format!("{}.csv", &store));
-
-        // println!("Saving file: {}", &file_name.to_string_lossy());
-
-        let mut wtr = csv::Writer::from_writer(File::create(&file_name)?);
-
-        for item in records.iter() {
-            debug!(
-                "The item being worked on: {} with UPC: {}",
-                &item.get(Field::Po.get()).unwrap().to_string(),
-                &item.get(Field::Upc.get()).unwrap().to_string(),
+    let mut parts = PartFiles::default();
+    let mut written: Vec<(Vec<PoNumber>, PathBuf, FileKind)> = vec![];
+
+    // Each group gets a file, and a ZPL file, of the lines of its stores, sorted by store. As
+    // `XlsxSheets`, the groups of each order are instead written to the sheets of one workbook.
+    // Packing lists and ship labels are always written for each store on its own.
+    let mut write_stores = || -> Result<()> {
+        // The sheets of the workbook of each order, by the path of the workbook.
+        let mut books: BTreeMap<PathBuf, Vec<SheetWriter>> = BTreeMap::new();
+        for group in &groups {
+            // As a sheet, the group is written to the workbook named after its order.
+            let stem = match outputs.format {
+                FileFormat::XlsxSheets => group.stores[0].base(),
+                _ => group.stem.as_str(),
+            };
+            let file_name = file_path.join(format!("{}.{}", stem, outputs.format.extension()));
+            info!(
+                "Writing {} store(s) to {}",
+                group.stores.len(),
+                file_name.display()
             );
+            progress.update(|state| state.current_file = Some(file_name.clone()));
+            let lines = group.stores.iter().flat_map(lines_of);
+            let rows = rows_of(lines, columns, plans, directory, outputs, progress)?;
+
+            match outputs.format {
+                FileFormat::Csv => {
+                    outputs.layout.write_csv(&rows, parts.create(&file_name)?)?;
+                    written.push((group.stores.clone(), file_name, FileKind::Order(None)));
+                }
+                FileFormat::Xlsx => {
+                    let sheet = outputs.layout.sheet(&group.stem, &rows)?;
+                    xlsx::save(vec![sheet], &parts.start(&file_name))?;
+                    written.push((group.stores.clone(), file_name, FileKind::Order(None)));
+                }
+                FileFormat::XlsxSheets => {
+                    // Sheets are named after the group, which may be cut to the same name as
+                    // another group of the order.
+                    let book = books.entry(file_name.clone()).or_default();
+                    let taken: Vec<String> = book.iter().map(SheetWriter::name).collect();
+                    let name = xlsx::unique_name(&group.stem, &taken);
+                    let sheet = outputs.layout.sheet(&name, &rows)?;
+                    let kind = FileKind::Order(Some(sheet.name()));
+                    written.push((group.stores.clone(), file_name, kind));
+                    book.push(sheet);
+                }
+            }
 
-            // If an item contains a `$` in the name description, then the qty should be set to `0`.
-            // See comments for `has_rfid()`.
-            if has_rfid(item)
-                && !print_all
-                && item.get(Field::Po.get()).unwrap().to_owned() == store
-            {
-                wtr.serialize(Order {
-                    po: item.get(Field::Po.get()).unwrap().to_owned(),
-                    style_code: item.get(Field::Style.get()).unwrap().to_owned(),
-                    color_code: item.get(Field::ColorCode.get()).unwrap().to_owned(),
-                    msrp_size: item.get(Field::Size.get()).unwrap().to_owned(),
-                    style_desc: item.get(Field::StyleDesc.get()).unwrap().to_owned(),
-                    color_desc: item.get(Field::ColorDesc.get()).unwrap().to_owned(),
-                    upc: item.get(Field::Upc.get()).unwrap().to_owned(),
-                    store_num: "".to_owned(), // This field must always be an empty string
-                    qty: "0".to_owned(),      // If it `has_rfid` is `true` then set qty to 0
-                })?;
-            } else if item.get(Field::Po.get()).unwrap().to_owned() == store {
-                wtr.serialize(Order {
-                    po: item.get(Field::Po.get()).unwrap().to_owned(),
-                    style_code: item.get(Field::Style.get()).unwrap().to_owned(),
-                    color_code: item.get(Field::ColorCode.get()).unwrap().to_owned(),
-                    msrp_size: item.get(Field::Size.get()).unwrap().to_owned(),
-                    style_desc: item.get(Field::StyleDesc.get()).unwrap().to_owned(),
-                    color_desc: item.get(Field::ColorDesc.get()).unwrap().to_owned(),
-                    upc: item.get(Field::Upc.get()).unwrap().to_owned(),
-                    store_num: "".to_owned(), // This field must always be an empty string
-                    qty: item.get(Field::Qty.get()).unwrap().to_owned(),
-                })?;
+            if let Some(template) = &outputs.zpl {
+                let file_name = file_path.join(format!("{}.zpl", group.stem));
+                write_labels(
+                    &mut BufWriter::new(parts.create(&file_name)?),
+                    group.stores.iter().flat_map(lines_of),
+                    columns,
+                    template,
+                    outputs.print_all,
+                    progress,
+                )?;
+                written.push((group.stores.clone(), file_name, FileKind::Labels));
+            }
+
+            for store in &group.stores {
+                let stem = grouping::file_stem(store, directory);
+                if let (true, Some(plan)) = (outputs.packing, plans.get(store)) {
+                    let file_name = file_path.join(format!("{}_packing.csv", stem));
+                    plan.write_csv(parts.create(&file_name)?)?;
+                    written.push((vec![store.clone()], file_name, FileKind::PackingList));
+
+                    let file_name = file_path.join(format!("{}_packing.txt", stem));
+                    plan.write_summary(parts.create(&file_name)?)?;
+                    written.push((vec![store.clone()], file_name, FileKind::PackingList));
+                }
+
+                if let (Some(encoder), Some(plan)) = (&outputs.sscc, plans.get(store)) {
+                    let file_name = file_path.join(format!("{}_ship.zpl", stem));
+                    let ship_to = directory
+                        .and_then(|directory| directory.get(store.store()))
+                        .map_or_else(Vec::new, |info| info.ship_to());
+                    let out = BufWriter::new(parts.create(&file_name)?);
+                    encoder.write_ship_labels(plan, &ship_to, out)?;
+                    written.push((vec![store.clone()], file_name, FileKind::ShipLabels));
+                }
+                progress.update(|state| state.stores_written += 1);
             }
         }
-        wtr.flush()?;
+
+        for (file_name, book) in books {
+            xlsx::save(book, &parts.start(&file_name))?;
+        }
+        Ok(())
+    };
+
+    if let Err(e) = write_stores() {
+        parts.remove();
+        return Err(e);
     }
+    parts.rename()?;
 
-    Ok(())
+    Ok(written)
 }
 
-/// Struct used when constructing a report
-struct Report {
-    num_stores: String,
-    total_labels: String,
-    with_rfid: String,
-    without_rfid: String,
-    boxes: String,
+/// The files of a split as they are written. Each file is written with a `.part` extension, and
+/// only renamed once every file has been written.
+#[derive(Debug, Default)]
+struct PartFiles {
+    /// The `.part` files started so far and the file each is renamed to.
+    parts: Vec<(PathBuf, PathBuf)>,
 }
 
-/// Produce a report of stores in a PO and the number of items
-fn produce_report(list_path: PathBuf, read_path: PathBuf) -> Result<Report> {
-    info!("Entering produce_report()");
-    let store_list: Vec<String> = list(list_path);
-    let results = read_file(read_path)?;
-    let results = filter_store(results, store_list)?;
-
-    #[derive(Debug)]
-    struct Store {
-        store_number: String,
-        qty_with_rfid: u32,
-        qty_without_rfid: u32,
-    }
-
-    let mut stores: Vec<Store> = Vec::new();
-    // Populate the Vec<Store> with the quantities of items with RFID and items without RFID
-    // to later produce the calculations below.
-    // TODO: The Store struct is poorly named because the information being stored is for each line of the CSV, not a specific store:
-    // TODO:   Item, Line, PO are better options.
-    for item in &results {
-        let po = item.get(0).unwrap().to_owned();
-        let qty: u32 = item.get(8).unwrap().parse()?;
-        let has_rfid: bool = has_rfid(&item);
-
-        let store = match has_rfid {
-            true => Store {
-                store_number: po,
-                qty_with_rfid: 0,
-                qty_without_rfid: qty,
+impl PartFiles {
+    /// Starts the file `file_name`, returning the path of its `.part` file to write to.
+    fn start(&mut self, file_name: &Path) -> PathBuf {
+        let mut part_name = file_name.as_os_str().to_owned();
+        part_name.push(".part");
+        let part_name = PathBuf::from(part_name);
+        self.parts.push((part_name.clone(), file_name.to_owned()));
+        part_name
+    }
+
+    /// Starts the file `file_name` and creates its `.part` file.
+    fn create(&mut self, file_name: &Path) -> Result<File> {
+        let part_name = self.start(file_name);
+        File::create(&part_name)
+            .with_context(|| format!("Failed to create file {}", part_name.display()))
+    }
+
+    /// Removes the `.part` file of every file started, once writing one of them failed.
+    fn remove(&self) {
+        for (part_name, _) in &self.parts {
+            match std::fs::remove_file(part_name) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    warn!("Failed to remove {}: {}", part_name.display(), e);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Renames the `.part` file of every file started to the file, failing with [PartlySaved]
+    /// once any of them was renamed.
+    fn rename(self) -> Result<()> {
+        let total = self.parts.len();
+        for (saved, (part_name, file_name)) in self.parts.into_iter().enumerate() {
+            let result = std::fs::rename(&part_name, &file_name)
+                .with_context(|| format!("Failed to save file {}", file_name.display()));
+            match result {
+                Err(e) if saved > 0 => return Err(e.context(PartlySaved { saved, total })),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The error of a split that failed after some of its files were saved, which may be printed
+/// from.
+#[derive(Debug)]
+struct PartlySaved {
+    saved: usize,
+    total: usize,
+}
+
+impl std::fmt::Display for PartlySaved {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "The split failed after {} of its {} files were saved",
+            self.saved, self.total
+        )
+    }
+}
+
+/// Returns the rows of `lines` in the columns of the layout of `outputs`, with a row for each
+/// label when `outputs` encodes EPCs. A line without labels, such as a pre-tagged item, still
+/// gets a row, with a qty of 0 and no EPC.
+fn rows_of<'a>(
+    lines: impl Iterator<Item = &'a Line>,
+    columns: &ColumnMap,
+    plans: &HashMap<PoNumber, PackingList>,
+    directory: Option<&StoreDirectory>,
+    outputs: &Outputs,
+    progress: &Progress,
+) -> Result<Vec<Vec<String>>> {
+    // The value of `field` on the row of `line`, or of its label with the EPC `epc` when each
+    // label gets a row of its own.
+    let output_field = |line: &Line, epc: Option<&str>, field: OutputField| -> String {
+        let info = directory.and_then(|directory| directory.get(line.po.store()));
+        match field {
+            // The file of a single store leaves the store number empty, a file of several
+            // stores needs it to tell their lines apart.
+            OutputField::StoreNum => match outputs.grouping {
+                Grouping::Store => "".to_owned(),
+                _ => line.po.store().to_owned(),
+            },
+            OutputField::Store => line.po.store().to_owned(),
+            OutputField::StoreName => info.map_or("", |info| info.name.as_str()).to_owned(),
+            OutputField::City => info.map_or("", |info| info.city.as_str()).to_owned(),
+            OutputField::Region => info.map_or("", |info| info.region.as_str()).to_owned(),
+            OutputField::Dc => info.map_or("", |info| info.dc.as_str()).to_owned(),
+            // If an item contains a `$` in the name description, then the qty should be set to `0`.
+            // See comments for `has_rfid()` and `Line::quantities()`.
+            OutputField::Qty => match epc {
+                Some(_) => "1".to_owned(),
+                None => line
+                    .quantities(outputs.print_all)
+                    .labels_to_print
+                    .to_string(),
             },
-            false => Store {
-                store_number: po,
-                qty_with_rfid: qty,
-                qty_without_rfid: 0,
+            OutputField::Epc => epc.unwrap_or_default().to_owned(),
+            OutputField::Box => plans.get(&line.po).map_or_else(String::new, |plan| {
+                plan.boxes_of(columns.get(&line.record, Field::Upc))
+            }),
+            OutputField::Rfid => match line.rfid.pre_tagged {
+                true => "Y".to_owned(),
+                false => "N".to_owned(),
             },
+        }
+    };
+
+    let mut rows = vec![];
+    for line in lines {
+        progress.check()?;
+        let item = &line.record;
+        debug!(
+            "The item being worked on: {} with UPC: {}",
+            columns.get(item, Field::Po),
+            columns.get(item, Field::Upc),
+        );
+
+        match outputs.epc.is_some() && !line.epcs.is_empty() {
+            // A row for each label, carrying its EPC.
+            true => {
+                for epc in &line.epcs {
+                    rows.push(
+                        outputs
+                            .layout
+                            .row(item, columns, |field| output_field(line, Some(epc), field)),
+                    );
+                }
+            }
+            false => rows.push(
+                outputs
+                    .layout
+                    .row(item, columns, |field| output_field(line, None, field)),
+            ),
+        }
+    }
+    Ok(rows)
+}
+
+/// Writes a label from `template` for every label to print of `lines`, so a line with a qty of
+/// 3 gets 3 labels, each with its own EPC. Lines with no labels to print, such as pre-tagged
+/// items, are skipped.
+fn write_labels<'a>(
+    out: &mut impl Write,
+    lines: impl Iterator<Item = &'a Line>,
+    columns: &ColumnMap,
+    template: &ZplTemplate,
+    print_all: bool,
+    progress: &Progress,
+) -> Result<()> {
+    for line in lines {
+        progress.check()?;
+        let item = &line.record;
+        let label = Label {
+            po: columns.get(item, Field::Po),
+            store: line.po.store(),
+            style: columns.get(item, Field::Style),
+            style_desc: columns.get(item, Field::StyleDesc),
+            color: columns.get(item, Field::ColorDesc),
+            color_code: columns.get(item, Field::ColorCode),
+            size: columns.get(item, Field::Size),
+            upc: columns.get(item, Field::Upc),
+            epc: "",
         };
 
-        stores.push(store);
+        for unit in 0..line.quantities(print_all).labels_to_print as usize {
+            let epc = line.epcs.get(unit).map_or("", |epc| epc.as_str());
+            out.write_all(template.render(&Label { epc, ..label }).as_bytes())?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Gives each label to print of `lines` an EPC, with serial numbers reserved from `serials` as
+/// `run`.
+///
+/// Every UPC is checked before any serial number is reserved, so a PO with a bad UPC uses up
+/// none.
+fn assign_epcs(
+    lines: &mut [Line],
+    columns: &ColumnMap,
+    encoder: &Sgtin96,
+    serials: &SerialAllocator,
+    print_all: bool,
+    run: &str,
+) -> Result<()> {
+    let mut requests = vec![];
+    let mut labeled = vec![];
+    for (index, line) in lines.iter().enumerate() {
+        let labels = line.quantities(print_all).labels_to_print;
+        if labels == 0 {
+            continue;
+        }
+
+        let upc = columns.get(&line.record, Field::Upc);
+        encoder
+            .encode(upc, 0)
+            .with_context(|| location(&line.record, columns, Field::Upc))?;
+        requests.push(Request {
+            gtin: epc::gtin14(upc)?,
+            count: labels as u64,
+            po: line.po.to_string(),
+            store: line.po.store().to_owned(),
+        });
+        labeled.push(index);
     }
 
-    // By using a HashSet, we remove all duplicated records from the vector.
-    // We acquire a set of unique POs that we can use as file names below.
-    let store_list = results
+    let reserved = serials.reserve(&requests, run)?;
+    info!(
+        "Reserved {} range(s) of serial numbers as run {}",
+        reserved.len(),
+        run
+    );
+
+    for (index, reservation) in labeled.into_iter().zip(reserved) {
+        let line = &mut lines[index];
+        let upc = columns.get(&line.record, Field::Upc);
+        for serial in reservation.first..=reservation.last {
+            line.epcs.push(encoder.encode(upc, serial)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Plans the cartons of each store of `lines`, each item packed in the carton `cartons` picks
+/// for it, see [PackingList::plan].
+fn plan_cartons(
+    lines: &[Line],
+    columns: &ColumnMap,
+    cartons: &Cartons,
+) -> HashMap<PoNumber, PackingList> {
+    let mut items: HashMap<PoNumber, Vec<(CartonClass, PackItem)>> = HashMap::new();
+    for line in lines {
+        let item = &line.record;
+        items.entry(line.po.clone()).or_default().push((
+            cartons.class(item),
+            PackItem {
+                upc: columns.get(item, Field::Upc).to_owned(),
+                style: columns.get(item, Field::Style).to_owned(),
+                color: columns.get(item, Field::ColorDesc).to_owned(),
+                size: columns.get(item, Field::Size).to_owned(),
+                qty: line.qty,
+            },
+        ));
+    }
+
+    items
+        .into_iter()
+        .map(|(po, items)| {
+            let plan = PackingList::plan(&po, items);
+            (po, plan)
+        })
+        .collect()
+}
+
+/// Gives each carton of `plans` an SSCC, with serial references reserved from `counter` as
+/// `run`.
+fn assign_ssccs(
+    plans: &mut HashMap<PoNumber, PackingList>,
+    encoder: &Sscc,
+    counter: &SerialAllocator,
+    run: &str,
+) -> Result<()> {
+    // The stores in PO order, so their cartons are numbered in that order.
+    let mut plans: Vec<&mut PackingList> = plans
+        .values_mut()
+        .filter(|plan| !plan.cartons.is_empty())
+        .collect();
+    plans.sort_by(|a, b| a.po.cmp(&b.po));
+
+    let requests: Vec<Request> = plans
         .iter()
-        .map(|num| num.get(0).unwrap().to_owned())
-        .collect::<HashSet<String>>();
+        .map(|plan| Request {
+            gtin: encoder.counter_key(),
+            count: plan.cartons.len() as u64,
+            po: plan.po.to_string(),
+            store: plan.po.store().to_owned(),
+        })
+        .collect();
+    let reserved = counter.reserve(&requests, run)?;
+    info!(
+        "Reserved SSCCs for the cartons of {} store(s) as run {}",
+        reserved.len(),
+        run
+    );
 
-    let mut total_with_rfid: u32 = 0;
-    let mut total_without_rfid: u32 = 0;
-    let mut total_stores: u32 = 0;
+    for (plan, reservation) in plans.into_iter().zip(reserved) {
+        for (carton, serial) in plan
+            .cartons
+            .iter_mut()
+            .zip(reservation.first..=reservation.last)
+        {
+            carton.sscc = Some(encoder.encode(serial)?);
+        }
+    }
 
-    for store_number in store_list {
-        let mut with_rfid: u32 = 0;
-        let mut without_rfid: u32 = 0;
+    Ok(())
+}
 
-        for store in &stores {
-            if store.store_number == store_number {
-                with_rfid = with_rfid + store.qty_with_rfid;
-                without_rfid = without_rfid + store.qty_without_rfid;
+/// Produce a report of stores in a PO and the number of items
+///
+/// The labels to print are counted as they would be written by splitting the PO with
+/// `print_all`.
+fn produce_report(
+    config: &Config,
+    profile: Option<&str>,
+    stores: &StoreSelection,
+    input: &PoInput,
+    print_all: bool,
+    progress: &Progress,
+) -> Result<Report> {
+    info!("Entering produce_report()");
+    let po_file = read_file(input, config, profile, progress)?;
+    let profile = po_file.profile;
+    let columns = po_file.columns;
+    let rules = profile.rfid_rules(&columns, config.pre_tagged_upcs()?)?;
+    let directory = store_directory(config, stores)?;
+    let results = filter_store(
+        po_file.records,
+        &columns,
+        &profile.po_pattern()?,
+        stores,
+        &rules,
+    )?;
+
+    Ok(build_report(
+        &results,
+        &columns,
+        print_all,
+        &profile.cartons(&columns)?,
+        directory.as_ref(),
+    ))
+}
+
+/// Reads the store directory of `config`, if there is one, and checks that it has every store
+/// of the store list of `stores`.
+fn store_directory(config: &Config, stores: &StoreSelection) -> Result<Option<StoreDirectory>> {
+    let directory = config.store_directory()?;
+    if let (Some(directory), StoreSelection::List(numbers)) = (&directory, stores) {
+        directory.check_list(numbers)?;
+    }
+    Ok(directory)
+}
+
+/// Builds the report of the `lines` of an order, each classified with [Line::quantities].
+///
+/// The items of each store are packed in the cartons picked by `cartons`, so the boxes of a
+/// store are counted for each carton on its own. Stores are named from the `directory`, and
+/// those missing from it are listed in the report.
+fn build_report(
+    lines: &[Line],
+    columns: &ColumnMap,
+    print_all: bool,
+    cartons: &Cartons,
+    directory: Option<&StoreDirectory>,
+) -> Report {
+    // Sum the labels to print and the pre-tagged items of each PO.
+    //
+    // By using a HashMap keyed by PO, each store ends up with a single entry.
+    let mut totals: HashMap<PoNumber, StoreTotals> = HashMap::new();
+    let mut packed: HashMap<(PoNumber, CartonClass), u32> = HashMap::new();
+    for line in lines {
+        let quantities = line.quantities(print_all);
+        let store = totals
+            .entry(line.po.clone())
+            .or_insert_with(|| StoreTotals::new(&line.po));
+
+        store.labels_to_print += quantities.labels_to_print;
+        store.pre_tagged += quantities.pre_tagged;
+        store.total += line.qty;
+        *packed
+            .entry((line.po.clone(), cartons.class(&line.record)))
+            .or_default() += line.qty;
+    }
+
+    let mut carton_totals: HashMap<String, CartonTotals> = HashMap::new();
+    for ((po, class), qty) in packed {
+        let boxes = report::boxes(qty, class.capacity);
+        if let Some(store) = totals.get_mut(&po) {
+            store.boxes += boxes;
+        }
+
+        let carton = carton_totals
+            .entry(class.name.clone())
+            .or_insert_with(|| CartonTotals {
+                carton: class.name,
+                capacity: class.capacity,
+                qty: 0,
+                boxes: 0,
+            });
+        carton.qty += qty;
+        carton.boxes += boxes;
+    }
+
+    let mut unknown_stores = vec![];
+    if let Some(directory) = directory {
+        for store in totals.values_mut() {
+            if let Some(info) = directory.get(&store.store) {
+                store.name = Some(info.name.clone());
+                store.city = Some(info.city.clone());
             }
         }
+        unknown_stores = directory.unknown(totals.values().map(|store| store.store.as_str()));
+    }
 
-        // Reports by store number
-        // TODO: Create custom Display for struct Report.
-        println!(
-            "Store {} - TOTAL: {}. WITH RFID: {} WITHOUT RFID: {}. |{} box(es)|",
-            store_number,
-            with_rfid + without_rfid,
-            without_rfid,
-            with_rfid,
-            ((with_rfid as f32 + without_rfid as f32) / 60.0).ceil()
-        );
+    Report::new(
+        totals.into_values().collect(),
+        carton_totals.into_values().collect(),
+        pre_tagged_lines(lines, columns, print_all),
+        unknown_stores,
+    )
+}
 
-        total_with_rfid = total_with_rfid + with_rfid;
-        total_without_rfid = total_without_rfid + without_rfid;
-        total_stores = total_stores + 1; // The total of unique store number in the PO
-    }
-
-    println!(
-        "\nTOTALS FOR THIS ORDER:
-        TOTAL STORES: {}
-        TOTAL LABELS: {}
-        NEEDS RFID PRINTED: {}
-        MAY NOT NEED RFID: {}
-        TOTAL BOXES: {}",
-        total_stores,
-        total_with_rfid + total_without_rfid,
-        total_with_rfid,
-        total_without_rfid,
-        ((total_with_rfid as f32 + total_without_rfid as f32) / 60.0).ceil()
-    );
-    Ok(Report {
-        num_stores: total_stores.to_string(),
-        total_labels: (total_with_rfid + total_without_rfid).to_string(),
-        with_rfid: total_with_rfid.to_string(),
-        without_rfid: total_without_rfid.to_string(),
-        boxes: ((total_with_rfid as f32 + total_without_rfid as f32) / 60.0)
-            .ceil()
-            .to_string(),
-    })
+/// Returns the quantity ordered on `record`.
+fn qty(record: &StringRecord, columns: &ColumnMap) -> Result<u32> {
+    let qty = columns.get(record, Field::Qty);
+    qty.parse()
+        .with_context(|| format!("The quantity `{}` is not a whole number", qty))
+        .with_context(|| location(record, columns, Field::Qty))
+}
+
+/// Returns the `lines` whose quantity is zeroed because they are pre-tagged, with the reason
+/// each is pre-tagged.
+fn pre_tagged_lines(lines: &[Line], columns: &ColumnMap, print_all: bool) -> Vec<PreTaggedLine> {
+    lines
+        .iter()
+        .filter(|line| line.quantities(print_all).pre_tagged > 0)
+        .filter_map(|line| {
+            line.rfid.reason.as_ref().map(|reason| PreTaggedLine {
+                line: location(&line.record, columns, Field::Qty).line,
+                po: line.po.to_string(),
+                upc: columns.get(&line.record, Field::Upc).to_owned(),
+                qty: line.qty,
+                reason: reason.clone(),
+            })
+        })
+        .collect()
 }
 
+/// What splitting a PO produced.
+#[derive(Debug)]
+struct Split {
+    /// The report of the files written, checked against the files by [reconcile].
+    report: Report,
+    /// The label files written, the CSV and ZPL files of each store, which can be sent to a
+    /// printer.
+    files: Vec<PathBuf>,
+}
+
+/// Splits the PO file into a file per store.
 fn produce_po_files(
-    list_path: PathBuf,
-    read_path: PathBuf,
+    config: &Config,
+    profile: Option<&str>,
+    stores: &StoreSelection,
+    input: &PoInput,
     output_path: PathBuf,
-    print_all: bool,
-) -> Result<()> {
+    outputs: &Outputs,
+    progress: &Progress,
+) -> Result<Split> {
     info!("Entering produce_po_files");
-    debug!("list_path: {}", &list_path.to_str().unwrap());
-    debug!("read_path: {}", &read_path.to_str().unwrap());
-    debug!("output_path: {}", &output_path.to_str().unwrap());
-    debug!("print_all: {}", &print_all);
+    debug!("stores: {:?}", &stores);
+    debug!("input: {:?}", &input);
+    debug!("output_path: {}", &output_path.display());
+    debug!("outputs: {:?}", &outputs);
+
+    let po_file = read_file(input, config, profile, progress)?;
+    let rules = po_file
+        .profile
+        .rfid_rules(&po_file.columns, config.pre_tagged_upcs()?)?;
+    let cartons = po_file.profile.cartons(&po_file.columns)?;
+    let directory = store_directory(config, stores)?;
+    let mut results = filter_store(
+        po_file.records,
+        &po_file.columns,
+        &po_file.profile.po_pattern()?,
+        stores,
+        &rules,
+    )?;
+    let report = build_report(
+        &results,
+        &po_file.columns,
+        outputs.print_all,
+        &cartons,
+        directory.as_ref(),
+    );
+    if !report.unknown_stores.is_empty() {
+        warn!("{}", report::unknown_summary(&report.unknown_stores));
+    }
+    let needs_plans =
+        outputs.packing || outputs.sscc.is_some() || outputs.layout.uses(OutputField::Box);
+    let mut plans = match needs_plans {
+        true => plan_cartons(&results, &po_file.columns, &cartons),
+        false => HashMap::new(),
+    };
+    // The process id sets the runs of two machines starting at the same time apart.
+    let run = format!(
+        "{}-{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"),
+        std::process::id()
+    );
+    let serials = SerialAllocator::new(&config.epc.serials);
+    let counter = SerialAllocator::new(&config.sscc.counter);
+
+    let split = || -> Result<Vec<(Vec<PoNumber>, PathBuf, FileKind)>> {
+        if let Some(encoder) = &outputs.epc {
+            assign_epcs(
+                &mut results,
+                &po_file.columns,
+                encoder,
+                &serials,
+                outputs.print_all,
+                &run,
+            )?;
+        }
+        if let Some(encoder) = &outputs.sscc {
+            let counter = counter.clone().with_max(encoder.max_serial());
+            assign_ssccs(&mut plans, encoder, &counter, &run)?;
+        }
 
-    let store_list: Vec<String> = list(list_path);
-    let results = read_file(read_path)?;
-    let results = filter_store(results, store_list)?;
-    match write_file(results, output_path, print_all) {
-        Result::Ok(_) => {
-            info!("write_file returned with Ok(), exciting produce_po_files");
-            Ok(())
+        write_file(
+            results,
+            &po_file.columns,
+            output_path,
+            &plans,
+            directory.as_ref(),
+            outputs,
+            progress,
+        )
+    };
+
+    let allocators = [
+        (outputs.epc.is_some(), &serials, "lisa serials"),
+        (outputs.sscc.is_some(), &counter, "lisa serials --sscc"),
+    ];
+    let used: Vec<_> = allocators.iter().filter(|(used, _, _)| *used).collect();
+    let written = match split() {
+        Result::Ok(written) => Result::Ok(written),
+        // Some files carry the serial numbers of the run, so they are kept as written.
+        Err(e) if e.is::<PartlySaved>() => {
+            for (_, _, command) in &used {
+                warn!(
+                    "Some files of run {} were saved, so its serial numbers are kept, see `{} --run {}`",
+                    run, command, run
+                );
+            }
+            Err(e)
+        }
+        // No file was written, so the serial numbers of the run can be handed out again.
+        Err(e) => {
+            for (_, allocator, command) in &used {
+                if let Err(release_error) = allocator.release(&run) {
+                    warn!(
+                        "Failed to release the serial numbers of run {}, release them with `{} --run {} --release`: {:?}",
+                        run, command, run, release_error
+                    );
+                }
+            }
+            return Err(e);
+        }
+    };
+    for (_, allocator, command) in &used {
+        if let Err(finish_error) = allocator.finish(&run) {
+            warn!(
+                "Failed to record the serial numbers of run {} as written, don't release them with `{} --run {} --release`: {:?}",
+                run, command, run, finish_error
+            );
+        }
+    }
+    let written = written?;
+    let orders: Vec<(&[PoNumber], &Path, Option<&str>)> = written
+        .iter()
+        .filter_map(|(pos, path, kind)| match kind {
+            FileKind::Order(sheet) => Some((pos.as_slice(), path.as_path(), sheet.as_deref())),
+            _ => None,
+        })
+        .collect();
+    report.reconcile(&orders, &outputs.layout)?;
+
+    for line in &report.pre_tagged_lines {
+        info!(
+            "Line {} ({} UPC {}) was zeroed by the {}",
+            line.line, line.po, line.upc, line.reason
+        );
+    }
+    info!("write_file returned with Ok(), exiting produce_po_files");
+    // The sheets of a workbook are listed as files of their own, but the workbook is printed once.
+    let mut files: Vec<PathBuf> = vec![];
+    for (_, path, kind) in written {
+        if matches!(kind, FileKind::Order(_) | FileKind::Labels) && !files.contains(&path) {
+            files.push(path);
         }
-        Err(e) => panic!("{}", e),
     }
+    Ok(Split { report, files })
 }
 
 #[derive(Debug, Default)]
 struct Gui {
+    config: Config,
+    /// The profile picked by the user, or `None` to detect it from the PO file.
+    profile: Option<String>,
     input: Option<PathBuf>,
+    /// The sheet of the input workbook to read, or `None` for its first sheet.
+    sheet: Option<String>,
+    /// The sheets of the input, empty unless it is a workbook.
+    sheets: Vec<String>,
+    /// The title rows above the header row of the input workbook, or `0` to find the header.
+    skip_rows: usize,
     output: Option<PathBuf>,
     list: Option<PathBuf>,
+    /// Process every store in the PO instead of the store list.
+    all_stores: bool,
+    /// The stores left out when processing every store, e.g. `045,101`.
+    exclude: String,
+    /// Print labels for pre-tagged items too.
+    print_all: bool,
+    /// Write ZPL labels next to the CSV files.
+    zpl: bool,
+    /// Encode an EPC for each label.
+    epc: bool,
+    /// Write a packing list for each store.
+    packing: bool,
+    /// Write a ship label with an SSCC for each carton.
+    ship_labels: bool,
+    /// How the stores are grouped into files.
+    grouping: Grouping,
+    /// The format of the file of each group.
+    format: FileFormat,
+    report_window: ReportWindow,
+    /// The outcome of the last run.
+    status: Status,
+    /// The job running in the background, if any.
+    job: Option<Job<Finished>>,
+    /// The printer the Print button prints to.
+    printer: Option<String>,
+    /// The files written by the last split, which the Print button sends to the printer.
+    printable: Vec<PathBuf>,
+}
+
+/// What a job run from the GUI produced.
+#[derive(Debug)]
+enum Finished {
+    /// The PO files were written, with the message to show.
+    PoFiles {
+        message: String,
+        files: Vec<PathBuf>,
+    },
+    Report(Report),
+    /// The files were sent to the printer, with the message to show.
+    Printed(String),
 }
 
 enum PathKind {
@@ -385,6 +1182,19 @@ impl Gui {
     fn put_path(&mut self, path: Option<PathBuf>, kind: PathKind) -> &mut Gui {
         match kind {
             PathKind::Input => {
+                self.sheet = None;
+                self.sheets = match &path {
+                    Some(path) if workbook::is_workbook(path) => {
+                        match workbook::sheet_names(path) {
+                            Result::Ok(sheets) => sheets,
+                            Err(e) => {
+                                self.status = Status::failed(&e);
+                                vec![]
+                            }
+                        }
+                    }
+                    _ => vec![],
+                };
                 self.input = path;
                 self
             }
@@ -406,6 +1216,326 @@ impl Gui {
             PathKind::List => self.list.as_ref(),
         }
     }
+
+    /// Returns the path of `kind` to be shown next to its button, or `placeholder` if it hasn't
+    /// been picked.
+    fn path_label(&mut self, kind: PathKind, placeholder: &str) -> String {
+        match Gui::get_path(self, kind) {
+            Some(path) => path.display().to_string(),
+            None => placeholder.to_owned(),
+        }
+    }
+
+    /// Checkbox to process every store in the PO, with a field for the stores to leave out.
+    fn all_stores_picker(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.all_stores, "All stores");
+        if self.all_stores {
+            ui.horizontal(|ui| {
+                ui.label("Except");
+                ui.text_edit_singleline(&mut self.exclude)
+                    .on_hover_text("Store numbers separated by commas, e.g. 045,101");
+            });
+        }
+    }
+
+    /// Returns the stores to process, or `None` if the list of stores hasn't been picked.
+    fn stores(&mut self) -> Result<Option<StoreSelection>> {
+        if self.all_stores {
+            return Ok(Some(StoreSelection::all_except(&self.exclude)?));
+        }
+
+        match Gui::get_path(self, PathKind::List) {
+            Some(path) => Ok(Some(StoreSelection::List(list(path.to_owned())?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns why a job can't be run with the inputs picked so far, or `None` if it can.
+    ///
+    /// Only the fields are checked; problems with the contents of the files are found when the
+    /// job runs.
+    fn invalid_input(&self, needs_output: bool) -> Option<String> {
+        if self.input.is_none() {
+            return Some(ErrorMsgBox::EmptyInputField.value().to_owned());
+        }
+        if needs_output && self.output.is_none() {
+            return Some(ErrorMsgBox::EmptyOutputField.value().to_owned());
+        }
+
+        match self.all_stores {
+            true => StoreSelection::all_except(&self.exclude)
+                .err()
+                .map(|e| format!("{:#}", e)),
+            false if self.list.is_none() => Some(ErrorMsgBox::EmptyListField.value().to_owned()),
+            false => None,
+        }
+    }
+
+    /// A button that can only be clicked once the inputs of its job are valid and no other job
+    /// is running. Otherwise the reason is shown below it.
+    fn run_button(&self, ui: &mut egui::Ui, text: &str, needs_output: bool) -> bool {
+        let invalid = self.invalid_input(needs_output);
+        let clicked = ui
+            .add_enabled(
+                invalid.is_none() && self.job.is_none(),
+                egui::Button::new(text),
+            )
+            .clicked();
+
+        if let Some(reason) = invalid {
+            ui.label(egui::RichText::new(reason).weak().small());
+        }
+        clicked
+    }
+
+    /// Returns the PO file to read, with the sheet and title rows picked for a workbook.
+    fn po_input(&self) -> Result<PoInput> {
+        let path = self
+            .input
+            .clone()
+            .context(ErrorMsgBox::EmptyInputField.value())?;
+        Ok(PoInput {
+            path,
+            sheet: self.sheet.clone(),
+            skip_rows: (self.skip_rows > 0).then_some(self.skip_rows),
+        })
+    }
+
+    /// Starts splitting the PO file into a file per store.
+    fn run_po_files(&mut self) -> Result<Job<Finished>> {
+        let input = self.po_input()?;
+        let output_path = self
+            .output
+            .clone()
+            .context(ErrorMsgBox::EmptyOutputField.value())?;
+        let stores = self
+            .stores()?
+            .context(ErrorMsgBox::EmptyListField.value())?;
+
+        let outputs = Outputs {
+            print_all: self.print_all,
+            zpl: self
+                .zpl
+                .then(|| self.config.zpl_template(self.epc))
+                .transpose()?,
+            epc: self.epc.then(|| self.config.epc_encoder()).transpose()?,
+            packing: self.packing,
+            sscc: self
+                .ship_labels
+                .then(|| self.config.sscc_encoder())
+                .transpose()?,
+            grouping: self.grouping,
+            format: self.format,
+            layout: self.config.output_layout(self.epc, self.grouping)?,
+        };
+        let config = self.config.clone();
+        let profile = self.profile.clone();
+        Ok(Job::spawn(move |progress| {
+            let split = produce_po_files(
+                &config,
+                profile.as_deref(),
+                &stores,
+                &input,
+                output_path.clone(),
+                &outputs,
+                progress,
+            )?;
+            let report = &split.report;
+            let mut message = format!(
+                "Saved {} label(s) to print for {} store(s) to {}. {}.",
+                report.labels_to_print,
+                report.num_stores,
+                output_path.display(),
+                report::zeroed_summary(&report.pre_tagged_lines)
+            );
+            if !report.unknown_stores.is_empty() {
+                message += &format!(" {}.", report::unknown_summary(&report.unknown_stores));
+            }
+            Ok(Finished::PoFiles {
+                message,
+                files: split.files,
+            })
+        }))
+    }
+
+    /// Starts sending the files of the last split to the picked printer.
+    fn run_print(&mut self) -> Result<Job<Finished>> {
+        let printer = self
+            .printer
+            .clone()
+            .context("Pick a printer to print to.")?;
+        if self.printable.is_empty() {
+            bail!("There is nothing to print, run a split first.");
+        }
+
+        let config = self.config.print.clone();
+        let files = self.printable.clone();
+        Ok(Job::spawn(move |_| {
+            Ok(Finished::Printed(print::print_files(
+                &config, &printer, &files,
+            )?))
+        }))
+    }
+
+    /// Dropdown of the printers in the config with a button to print the files of the last split.
+    fn print_picker(&mut self, ui: &mut egui::Ui) {
+        let names: Vec<String> = self
+            .config
+            .print
+            .printers
+            .iter()
+            .map(|printer| printer.name.clone())
+            .collect();
+        if names.is_empty() {
+            return;
+        }
+
+        egui::ComboBox::from_id_source("printer")
+            .selected_text(self.printer.as_deref().unwrap_or("Printer"))
+            .show_ui(ui, |ui| {
+                for name in names {
+                    ui.selectable_value(&mut self.printer, Some(name.clone()), name);
+                }
+            });
+        let enabled = self.printer.is_some() && !self.printable.is_empty() && self.job.is_none();
+        if ui
+            .add_enabled(enabled, egui::Button::new("Print"))
+            .on_disabled_hover_text("Pick a printer and run a split first")
+            .clicked()
+        {
+            self.start(Gui::run_print);
+        }
+    }
+
+    /// Starts producing the report of the PO file.
+    fn run_report(&mut self) -> Result<Job<Finished>> {
+        let input = self.po_input()?;
+        let stores = self
+            .stores()?
+            .context(ErrorMsgBox::EmptyListField.value())?;
+
+        let print_all = self.print_all;
+        let config = self.config.clone();
+        let profile = self.profile.clone();
+        Ok(Job::spawn(move |progress| {
+            let report = produce_report(
+                &config,
+                profile.as_deref(),
+                &stores,
+                &input,
+                print_all,
+                progress,
+            )?;
+            Ok(Finished::Report(report))
+        }))
+    }
+
+    /// Runs the job returned by `start`, or shows why it couldn't be started.
+    fn start(&mut self, start: impl FnOnce(&mut Gui) -> Result<Job<Finished>>) {
+        match start(self) {
+            Result::Ok(job) => {
+                self.status = Status::Idle;
+                self.job = Some(job);
+            }
+            Err(e) => self.status = Status::failed(&e),
+        }
+    }
+
+    /// Shows the progress of the running job with a button to cancel it, and the outcome of the
+    /// job once it's finished.
+    fn job_panel(&mut self, ctx: &egui::Context) {
+        let job = match self.job.take() {
+            Some(job) if job.is_finished() => {
+                self.status = match job.join() {
+                    Result::Ok(Finished::PoFiles { message, files }) => {
+                        self.printable = files;
+                        Status::Done(message)
+                    }
+                    Result::Ok(Finished::Printed(message)) => Status::Done(message),
+                    Result::Ok(Finished::Report(report)) => {
+                        self.report_window.set_report(report);
+                        Status::Idle
+                    }
+                    Err(e) if e.is::<Cancelled>() => {
+                        Status::Done("Cancelled, no files were written.".to_owned())
+                    }
+                    Err(e) => Status::failed(&e),
+                };
+                return;
+            }
+            Some(job) => job,
+            None => return,
+        };
+
+        let progress = job.progress().state();
+        egui::TopBottomPanel::bottom("job_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let cancelling = job.progress().is_cancelled();
+                if ui
+                    .add_enabled(!cancelling, egui::Button::new("Cancel"))
+                    .clicked()
+                {
+                    job.progress().cancel();
+                }
+                let text = match cancelling {
+                    true => "Cancelling...".to_owned(),
+                    false => progress.to_string(),
+                };
+                ui.add(egui::ProgressBar::new(progress.fraction()).text(text));
+            });
+        });
+
+        // Keep redrawing while the job runs, so its progress is shown without waiting for input.
+        ctx.request_repaint();
+        self.job = Some(job);
+    }
+
+    /// Dropdown to override the profile detected from the PO file.
+    fn profile_picker(&mut self, ui: &mut egui::Ui, id: &str) {
+        let names: Vec<String> = self
+            .config
+            .all_profiles()
+            .into_iter()
+            .map(|profile| profile.name)
+            .collect();
+
+        ui.horizontal(|ui| {
+            ui.label("Profile");
+            egui::ComboBox::from_id_source(id)
+                .selected_text(self.profile.as_deref().unwrap_or("Automatic"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.profile, None, "Automatic");
+                    for name in names {
+                        ui.selectable_value(&mut self.profile, Some(name.clone()), name);
+                    }
+                });
+        });
+    }
+
+    /// Dropdown of the sheets of the input workbook, with the title rows above its header.
+    /// Nothing is shown unless the input is a workbook.
+    fn sheet_picker(&mut self, ui: &mut egui::Ui, id: &str) {
+        if self.sheets.is_empty() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Sheet");
+            egui::ComboBox::from_id_source(id)
+                .selected_text(self.sheet.as_deref().unwrap_or("First sheet"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.sheet, None, "First sheet");
+                    for name in &self.sheets {
+                        ui.selectable_value(&mut self.sheet, Some(name.clone()), name);
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Title rows");
+            ui.add(egui::DragValue::new(&mut self.skip_rows).clamp_range(0..=100))
+                .on_hover_text("Rows above the header row, found by looking for it when 0");
+        });
+    }
 }
 
 /// The implementation of Lisa's GUI mode.
@@ -413,6 +1543,9 @@ impl eframe::App for Gui {
     // TODO: Major need for refactoring. Move logic out of GUI code.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // let mut paths = Gui::default();
+        self.job_panel(ctx);
+        self.status.show(ctx);
+        self.report_window.show(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
             // Top Menu Bar
             egui::menu::bar(ui, |ui| {
@@ -433,18 +1566,15 @@ impl eframe::App for Gui {
                         ui.horizontal(|ui| {
                             if ui.button("Input").clicked() {
                                 let path = rfd::FileDialog::new()
-                                    .add_filter("csv", &["csv", "txt"])
+                                    .add_filter("PO file", &PO_EXTENSIONS)
                                     .set_title("Select input file...")
                                     .pick_file();
 
                                 Gui::put_path(self, path, PathKind::Input);
                             }
-                            let path = match Gui::get_path(self, PathKind::Input) {
-                                Some(path) => path.to_str().unwrap(),
-                                None => "Select a PO file.",
-                            };
-                            ui.label(path);
+                            ui.label(self.path_label(PathKind::Input, "Select a PO file."));
                         });
+                        self.sheet_picker(ui, "run_sheet");
 
                         // Layout holding the Output button and text field
                         ui.horizontal(|ui| {
@@ -455,66 +1585,67 @@ impl eframe::App for Gui {
 
                                 Gui::put_path(self, path, PathKind::Output);
                             }
-
-                            let path = match Gui::get_path(self, PathKind::Output) {
-                                Some(path) => path.to_str().unwrap(),
-                                None => "Select a destination.",
-                            };
-                            ui.label(path);
+                            ui.label(self.path_label(PathKind::Output, "Select a destination."));
                         });
 
                         // Layout holding the List button and text field
-                        ui.horizontal(|ui| {
-                            if ui.button("List").clicked() {
-                                let path = rfd::FileDialog::new()
-                                    .set_title("Select list of stores...")
-                                    .pick_file();
-
-                                Gui::put_path(self, path, PathKind::List);
-                            }
-                            let path = match Gui::get_path(self, PathKind::List) {
-                                Some(path) => path.to_str().unwrap(),
-                                None => "Select list of stores",
-                            };
-                            ui.label(path);
+                        ui.add_enabled_ui(!self.all_stores, |ui| {
+                            ui.horizontal(|ui| {
+                                if ui.button("List").clicked() {
+                                    let path = rfd::FileDialog::new()
+                                        .set_title("Select list of stores...")
+                                        .pick_file();
+
+                                    Gui::put_path(self, path, PathKind::List);
+                                }
+                                ui.label(self.path_label(PathKind::List, "Select list of stores"));
+                            });
                         });
 
+                        self.all_stores_picker(ui);
+                        self.profile_picker(ui, "run_profile");
+                        ui.checkbox(&mut self.print_all, "Print all")
+                            .on_hover_text("Print labels for pre-tagged items too");
+                        ui.checkbox(&mut self.zpl, "ZPL labels")
+                            .on_hover_text("Also write a file of Zebra labels for each store");
+                        ui.checkbox(&mut self.epc, "EPCs")
+                            .on_hover_text("Encode an SGTIN-96 EPC for each label");
+                        ui.checkbox(&mut self.packing, "Packing lists")
+                            .on_hover_text("Also write what goes in each box of each store");
+                        ui.checkbox(&mut self.ship_labels, "Ship labels")
+                            .on_hover_text("Also write an SSCC ship label for each box");
+                        egui::ComboBox::from_id_source("grouping")
+                            .selected_text(self.grouping.label())
+                            .show_ui(ui, |ui| {
+                                for grouping in Grouping::ALL {
+                                    ui.selectable_value(
+                                        &mut self.grouping,
+                                        grouping,
+                                        grouping.label(),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Group the stores by the DC or region of the store directory",
+                            );
+                        egui::ComboBox::from_id_source("file_format")
+                            .selected_text(self.format.label())
+                            .show_ui(ui, |ui| {
+                                for format in FileFormat::ALL {
+                                    ui.selectable_value(&mut self.format, format, format.label());
+                                }
+                            })
+                            .response
+                            .on_hover_text("Write the files as CSV, or as XLSX with UPCs as text");
+
                         ui.vertical_centered(|ui| {
-                            if ui.button("Run").clicked() {
-                                let read_path = match Gui::get_path(self, PathKind::Input) {
-                                    Some(path) => path.to_owned(),
-                                    None => {
-                                        lisa::message_box::empty_field(
-                                            ErrorMsgBox::EmptyInputField,
-                                        );
-                                        panic!("Input field can not be empty.");
-                                        // TODO: Replace with proper error handling.
-                                    }
-                                };
-
-                                let output_path = match Gui::get_path(self, PathKind::Output) {
-                                    Some(path) => path.to_owned(),
-                                    None => {
-                                        lisa::message_box::empty_field(
-                                            ErrorMsgBox::EmptyOutputField,
-                                        );
-                                        panic!("Output field can not be empty.");
-                                        // TODO: Replace with proper error handling.
-                                    }
-                                };
-                                let list_path = match Gui::get_path(self, PathKind::List) {
-                                    Some(path) => path.to_owned(),
-                                    None => {
-                                        lisa::message_box::empty_field(ErrorMsgBox::EmptyListField);
-                                        panic!("List field can not be empty."); // TODO: Replace with proper error handling.
-                                    }
-                                };
-
-                                let print_all = false;
-                                let _results =
-                                    produce_po_files(list_path, read_path, output_path, print_all)
-                                        .context("Something went wrong while 'produce_po_files()'");
-                            }
+                            ui.horizontal(|ui| {
+                                if self.run_button(ui, "Run", true) {
+                                    self.start(Gui::run_po_files);
+                                }
+                                self.print_picker(ui);
+                            });
                         });
 
                         ui.vertical_centered(|ui| ui.heading("Reports"));
@@ -522,41 +1653,41 @@ impl eframe::App for Gui {
                         ui.horizontal(|ui| {
                             if ui.button("Input").clicked() {
                                 let path = rfd::FileDialog::new()
-                                    .add_filter("csv", &["csv", "txt"])
+                                    .add_filter("PO file", &PO_EXTENSIONS)
                                     .set_title("Select input file...")
                                     .pick_file();
 
                                 Gui::put_path(self, path, PathKind::Input);
                             }
-                            let path = match Gui::get_path(self, PathKind::Input) {
-                                Some(path) => path.to_str().unwrap(),
-                                None => "Select a PO file.",
-                            };
-                            ui.label(path);
+                            ui.label(self.path_label(PathKind::Input, "Select a PO file."));
                         });
+                        self.sheet_picker(ui, "report_sheet");
                         // Layout holding the Input button and text fields for Reports
-                        ui.horizontal(|ui| {
-                            if ui.button("List").clicked() {
-                                let path = rfd::FileDialog::new()
-                                    .set_title("Select list of stores...")
-                                    .pick_file();
-
-                                Gui::put_path(self, path, PathKind::List);
-                            }
-                            let path = match Gui::get_path(self, PathKind::List) {
-                                Some(path) => path.to_str().unwrap(),
-                                None => "Select list of stores",
-                            };
-                            ui.label(path);
+                        ui.add_enabled_ui(!self.all_stores, |ui| {
+                            ui.horizontal(|ui| {
+                                if ui.button("List").clicked() {
+                                    let path = rfd::FileDialog::new()
+                                        .set_title("Select list of stores...")
+                                        .pick_file();
+
+                                    Gui::put_path(self, path, PathKind::List);
+                                }
+                                ui.label(self.path_label(PathKind::List, "Select list of stores"));
+                            });
                         });
 
+                        ui.push_id("report_stores", |ui| self.all_stores_picker(ui));
+                        self.profile_picker(ui, "report_profile");
+                        ui.checkbox(&mut self.print_all, "Print all")
+                            .on_hover_text("Count pre-tagged items as labels to print");
+
                         ui.vertical_centered(|ui| {
-                            if ui.button("Run Reports").clicked() {
-                                let list_path =
-                                    Gui::get_path(self, PathKind::List).unwrap().to_owned();
-                                let read_path =
-                                    Gui::get_path(self, PathKind::Input).unwrap().to_owned();
-                                produce_report(list_path, read_path);
+                            if self.run_button(ui, "Run Reports", false) {
+                                self.start(Gui::run_report);
+                            }
+                            if self.report_window.has_report() && ui.button("Show Report").clicked()
+                            {
+                                self.report_window.open();
                             }
                         });
                     })
@@ -566,53 +1697,282 @@ impl eframe::App for Gui {
 }
 
 /// Function that calls for Lisa's GUI mode to run.
-fn run_gui() {
+fn run_gui(config: Config) -> ! {
     let options = eframe::NativeOptions {
         drag_and_drop_support: true,
         ..Default::default()
     };
-    eframe::run_native("LISA", options, Box::new(|_cc| Box::new(Gui::default())));
+    let gui = Gui {
+        config,
+        ..Default::default()
+    };
+    eframe::run_native("LISA", options, Box::new(|_cc| Box::new(gui)));
 }
 
 /// Cli holds all possible user flags that is parsed by [Calp](https://docs.rs/clap/latest/clap/).
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
 struct Cli {
-    /// The PO csv file to be used
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// The PO file to be used, a CSV file or an XLSX or XLS workbook
     #[clap(short, long, parse(from_os_str), required_unless_present = "gui")]
     input: Option<PathBuf>,
+    /// The sheet of the workbook to read, the first sheet when omitted
+    #[clap(long)]
+    sheet: Option<String>,
+    /// The title rows above the header row of the workbook, found by looking for the header when
+    /// omitted
+    #[clap(long = "skip-rows")]
+    skip_rows: Option<usize>,
     /// The destination directory where the processed POs will be saved
     #[clap(short, long, parse(from_os_str), required_unless_present_any = &["gui", "report"])]
     output: Option<PathBuf>,
-    /// The text file that contains all of the store numbers to be processed
-    #[clap(short, long, parse(from_os_str), required_unless_present = "gui")]
+    /// The TOML or text file that contains all of the store numbers to be processed
+    #[clap(short, long, parse(from_os_str), required_unless_present_any = &["gui", "all-stores"])]
     list: Option<PathBuf>,
-    /// Print all RFIDs including items marked with a '$'
+    /// Process every store found in the PO instead of a list of stores
+    #[clap(long = "all-stores", conflicts_with = "list")]
+    all_stores: bool,
+    /// Store numbers to leave out when processing all stores, e.g. `045,101`
+    #[clap(short = 'x', long, conflicts_with = "list", requires = "all-stores")]
+    exclude: Option<String>,
+    /// Print all RFIDs including items that are pre-tagged, e.g. marked with a '$'
     #[clap(short = 'a', long = "print-all")]
     printall: bool,
+    /// Also write a file of ZPL labels for each store, from the `[zpl]` template of the config
+    #[clap(long, conflicts_with = "report")]
+    zpl: bool,
+    /// Encode an SGTIN-96 EPC for each label, set up by the `[epc]` table of the config
+    #[clap(long, conflicts_with = "report")]
+    epc: bool,
+    /// Also write a packing list for each store, the items that go in each of its boxes
+    #[clap(long, conflicts_with = "report")]
+    packing: bool,
+    /// Also write a ship label with an SSCC for each box, set up by the `[sscc]` table of the
+    /// config
+    #[clap(long = "ship-labels", conflicts_with = "report")]
+    ship_labels: bool,
+    /// How the stores are grouped into files. `dc` and `region` need a store directory
+    #[clap(
+        short,
+        long,
+        arg_enum,
+        default_value = "store",
+        conflicts_with = "report"
+    )]
+    group: Grouping,
+    /// The format of the files. `xlsx-sheets` writes one workbook per order with a sheet for
+    /// each file
+    #[clap(long, arg_enum, default_value = "csv", conflicts_with = "report")]
+    format: FileFormat,
+    /// Send the labels to the printer with this name from the `[print]` table of the config
+    #[clap(long, conflicts_with = "report")]
+    print: Option<String>,
     /// Produce a report of selected PO
-    #[clap(short, long, conflicts_with_all = &["printall"])]
+    #[clap(short, long)]
     report: bool,
+    /// The profile describing the layout of the PO file, detected from its header when omitted
+    #[clap(short, long)]
+    profile: Option<String>,
+    /// The TOML file that configures LISA, defaults to `lisa.toml` when it exists
+    #[clap(short, long, parse(from_os_str), global = true)]
+    config: Option<PathBuf>,
     /// Runs LISA in GUI mode
     #[clap(long = "gui", exclusive = true)]
     gui: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Produce a report of the stores in a PO and the number of items for each
+    Report(ReportArgs),
+    /// List the EPC serial numbers or SSCCs reserved, or release those of a run that printed
+    /// nothing
+    Serials(SerialsArgs),
+    /// Send label files to a printer from the `[print]` table of the config
+    Print(PrintArgs),
+}
+
+/// The flags of the `print` subcommand.
+#[derive(Args)]
+struct PrintArgs {
+    /// The name of the printer
+    #[clap(short, long)]
+    printer: String,
+    /// The files to print. Files the printer doesn't take, by its `format`, are skipped
+    #[clap(parse(from_os_str), required = true)]
+    files: Vec<PathBuf>,
+}
+
+/// The flags of the `serials` subcommand.
+#[derive(Args)]
+struct SerialsArgs {
+    /// Only the ranges reserved by this run, e.g. `20220701-143000.123-4242`
+    #[clap(long)]
+    run: Option<String>,
+    /// Only the ranges reserved for this PO, e.g. `po14423-001`
+    #[clap(long)]
+    po: Option<String>,
+    /// Only the ranges of this UPC or GTIN
+    #[clap(long, conflicts_with = "sscc")]
+    gtin: Option<String>,
+    /// The serial references of the SSCCs of cartons instead of the serial numbers of EPCs
+    #[clap(long)]
+    sscc: bool,
+    /// Release the ranges of the run, so serial numbers that were never printed aren't used up.
+    /// The ranges of a run that wrote its files are never released
+    #[clap(long, requires = "run", conflicts_with_all = &["po", "gtin"])]
+    release: bool,
+}
+
+impl SerialsArgs {
+    /// Lists or releases the selected ranges of the serials file, or SSCC counter, of `config`.
+    fn run(&self, config: &Config) -> Result<()> {
+        let serials = match self.sscc {
+            true => SerialAllocator::new(&config.sscc.counter),
+            false => SerialAllocator::new(&config.epc.serials),
+        };
+        let selection = Selection {
+            run: self.run.clone(),
+            po: self.po.clone(),
+            gtin: self.gtin.as_deref().map(epc::gtin14).transpose()?,
+        };
+
+        if !self.release {
+            let reservations: Vec<_> = serials
+                .reservations()?
+                .into_iter()
+                .filter(|reservation| selection.matches(reservation))
+                .collect();
+            print!("{}", serials::table(&reservations));
+            return Ok(());
+        }
+
+        let released = serials.release(self.run.as_deref().unwrap_or_default())?;
+        println!("Released {} range(s)", released.len());
+        print!("{}", serials::table(&released));
+        Ok(())
+    }
+}
+
+/// The flags of the `report` subcommand.
+#[derive(Args)]
+struct ReportArgs {
+    /// The PO file to be used, a CSV file or an XLSX or XLS workbook
+    #[clap(short, long, parse(from_os_str))]
+    input: PathBuf,
+    /// The sheet of the workbook to read, the first sheet when omitted
+    #[clap(long)]
+    sheet: Option<String>,
+    /// The title rows above the header row of the workbook, found by looking for the header when
+    /// omitted
+    #[clap(long = "skip-rows")]
+    skip_rows: Option<usize>,
+    /// The TOML or text file that contains all of the store numbers to be reported
+    #[clap(
+        short,
+        long,
+        parse(from_os_str),
+        required_unless_present = "all-stores"
+    )]
+    list: Option<PathBuf>,
+    /// Report every store found in the PO instead of a list of stores
+    #[clap(long = "all-stores", conflicts_with = "list")]
+    all_stores: bool,
+    /// Store numbers to leave out when reporting all stores, e.g. `045,101`
+    #[clap(short = 'x', long, conflicts_with = "list", requires = "all-stores")]
+    exclude: Option<String>,
+    /// The profile describing the layout of the PO file, detected from its header when omitted
+    #[clap(short, long)]
+    profile: Option<String>,
+    /// Count pre-tagged items as labels to print, as a split with `--print-all` would
+    #[clap(short = 'a', long = "print-all")]
+    print_all: bool,
+    /// The format the report is written in
+    #[clap(short, long, arg_enum, default_value = "table")]
+    format: ReportFormat,
+    /// The file the report is saved to, printed when omitted. `xlsx` reports need one
+    #[clap(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl ReportArgs {
+    fn stores(&self) -> Result<StoreSelection> {
+        match self.all_stores {
+            true => StoreSelection::all_except(self.exclude.as_deref().unwrap_or_default()),
+            false => Ok(StoreSelection::List(list(
+                self.list.clone().unwrap_or_default(),
+            )?)),
+        }
+    }
+
+    fn input(&self) -> PoInput {
+        PoInput {
+            path: self.input.clone(),
+            sheet: self.sheet.clone(),
+            skip_rows: self.skip_rows,
+        }
+    }
+}
+
 /// Runs Lisa
 fn run_app() -> Result<()> {
     info!("[run_app] Entering run_app()");
     let args = Cli::parse();
+    let config = Config::load_or_default(args.config)?;
 
     // Run is_gui first to avoid
     let is_gui: bool = args.gui;
     if is_gui {
-        run_gui();
+        run_gui(config);
+    }
+
+    if let Some(Command::Report(report_args)) = &args.command {
+        let report = produce_report(
+            &config,
+            report_args.profile.as_deref(),
+            &report_args.stores()?,
+            &report_args.input(),
+            report_args.print_all,
+            &Progress::default(),
+        )?;
+        let contents = report.render(report_args.format)?;
+        match &report_args.output {
+            Some(path) => std::fs::write(path, contents)
+                .with_context(|| format!("Failed to save the report to {}", path.display()))?,
+            None if report_args.format == ReportFormat::Xlsx => {
+                bail!("Give the file to save an XLSX report to with `--output`")
+            }
+            None => print!("{}", String::from_utf8(contents)?),
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Serials(serials_args)) = &args.command {
+        return serials_args.run(&config);
+    }
+
+    if let Some(Command::Print(print_args)) = &args.command {
+        println!(
+            "{}",
+            print::print_files(&config.print, &print_args.printer, &print_args.files)?
+        );
+        return Ok(());
     }
 
     // Default behavior is not to print items that contain a '$' at the end of the line
-    let list_path: PathBuf = args.list.unwrap_or_default();
+    let stores = match args.all_stores {
+        true => StoreSelection::all_except(&args.exclude.unwrap_or_default())?,
+        false => StoreSelection::List(list(args.list.unwrap_or_default())?),
+    };
     let output_path: PathBuf = args.output.unwrap_or_default();
-    let read_path: PathBuf = args.input.unwrap_or_default();
+    let input = PoInput {
+        path: args.input.unwrap_or_default(),
+        sheet: args.sheet,
+        skip_rows: args.skip_rows,
+    };
     let print_all: bool = args.printall;
     let is_report: bool = args.report;
 
@@ -620,12 +1980,56 @@ fn run_app() -> Result<()> {
     debug!("[run_app] is_gui is set to: {}", &is_gui);
 
     match is_report {
-        // TODO: Fix the `true` arm: produce_report() is returning a Report.
         true => {
-            // produce_report(list_path, read_path)?
-            panic!("\nReports is currently unavailable from the command line.\nUse Lisa in GUI mode to use reports.\n\t Run: Lisa --gui\n");
+            let report = produce_report(
+                &config,
+                args.profile.as_deref(),
+                &stores,
+                &input,
+                print_all,
+                &Progress::default(),
+            )?;
+            print!("{}", report);
+        }
+        false => {
+            let split = produce_po_files(
+                &config,
+                args.profile.as_deref(),
+                &stores,
+                &input,
+                output_path,
+                &Outputs {
+                    print_all,
+                    zpl: args
+                        .zpl
+                        .then(|| config.zpl_template(args.epc))
+                        .transpose()?,
+                    epc: args.epc.then(|| config.epc_encoder()).transpose()?,
+                    packing: args.packing,
+                    sscc: args
+                        .ship_labels
+                        .then(|| config.sscc_encoder())
+                        .transpose()?,
+                    grouping: args.group,
+                    format: args.format,
+                    layout: config.output_layout(args.epc, args.group)?,
+                },
+                &Progress::default(),
+            )?;
+            let report = &split.report;
+            if !report.pre_tagged_lines.is_empty() {
+                println!("{}", report::zeroed_summary(&report.pre_tagged_lines));
+            }
+            if !report.unknown_stores.is_empty() {
+                println!("{}", report::unknown_summary(&report.unknown_stores));
+            }
+            if let Some(printer) = &args.print {
+                println!(
+                    "{}",
+                    print::print_files(&config.print, printer, &split.files)?
+                );
+            }
         }
-        false => produce_po_files(list_path, read_path, output_path, print_all)?,
     }
 
     Ok(())
@@ -0,0 +1,257 @@
+use crate::columns::{Column, ColumnMap, Field};
+use crate::grouping::Grouping;
+use crate::xlsx::SheetWriter;
+use anyhow::{bail, Result};
+use csv::StringRecord;
+use serde::Deserialize;
+use std::io::Write;
+
+/// The values LISA works out for each row of a split file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputField {
+    /// The store number in a file of several stores, empty in the file of a single store.
+    StoreNum,
+    /// The store number.
+    Store,
+    /// The name of the store from the store directory.
+    StoreName,
+    /// The city of the store from the store directory.
+    City,
+    /// The region of the store from the store directory.
+    Region,
+    /// The distribution center of the store from the store directory.
+    Dc,
+    /// The labels to print of the line, or `1` on the row of each EPC.
+    Qty,
+    Epc,
+    /// The boxes of the packing list the item is packed in, e.g. `1` or `2+3`.
+    Box,
+    /// `Y` if the item is pre-tagged, `N` if it needs a label.
+    Rfid,
+}
+
+/// Where the values of a column of the split files come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A column of the PO file, copied as is.
+    Column(Column),
+    Field(OutputField),
+    /// The same value on every row.
+    Value(String),
+}
+
+/// A column of the split files as written in the config.
+///
+/// Exactly one of `column`, `field` and `value` is given.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputColumn {
+    /// The header of the column.
+    pub header: String,
+    /// A column of the PO file, one of the column keys, e.g. `upc`, or any other header.
+    pub column: Option<Column>,
+    /// A value LISA works out, see [OutputField].
+    pub field: Option<OutputField>,
+    pub value: Option<String>,
+}
+
+/// The columns of the split files, e.g.
+///
+/// ```toml
+/// [[output.column]]
+/// header = "UPC Code"
+/// column = "upc"
+///
+/// [[output.column]]
+/// header = "Quantity"
+/// field = "qty"
+///
+/// [[output.column]]
+/// header = "Vendor"
+/// value = "ACME"
+/// ```
+///
+/// Without any column the files are written in LISA's own layout, see
+/// [OutputLayout::standard].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(rename = "column")]
+    pub columns: Vec<OutputColumn>,
+}
+
+/// The checked columns of the split files, each with its header.
+#[derive(Debug, Clone)]
+pub struct OutputLayout {
+    columns: Vec<(String, Source)>,
+}
+
+impl Default for OutputLayout {
+    fn default() -> Self {
+        OutputLayout::standard(false)
+    }
+}
+
+impl OutputLayout {
+    /// The layout the Infinity app imports, with an `Epc` column when `epc` is set.
+    pub fn standard(epc: bool) -> OutputLayout {
+        let mut columns = vec![
+            ("Po", Source::Column(Column::Field(Field::Po))),
+            ("StyleCode", Source::Column(Column::Field(Field::Style))),
+            ("ColorCode", Source::Column(Column::Field(Field::ColorCode))),
+            ("MsrpSize", Source::Column(Column::Field(Field::Size))),
+            ("StyleDesc", Source::Column(Column::Field(Field::StyleDesc))),
+            ("ColorDesc", Source::Column(Column::Field(Field::ColorDesc))),
+            ("Upc", Source::Column(Column::Field(Field::Upc))),
+            ("StoreNum", Source::Field(OutputField::StoreNum)),
+            ("Qty", Source::Field(OutputField::Qty)),
+        ];
+        if epc {
+            columns.push(("Epc", Source::Field(OutputField::Epc)));
+        }
+
+        OutputLayout {
+            columns: columns
+                .into_iter()
+                .map(|(header, source)| (header.to_owned(), source))
+                .collect(),
+        }
+    }
+
+    /// Checks the columns of `config` for a split that encodes EPCs when `epc` is set, and groups
+    /// stores by `grouping`.
+    ///
+    /// Each row must say how many labels it is for, so the layout needs a `qty` field unless
+    /// each row is the label of an EPC. A file of several stores needs the store of each row.
+    pub fn new(config: &OutputConfig, epc: bool, grouping: Grouping) -> Result<OutputLayout> {
+        if config.columns.is_empty() {
+            return Ok(OutputLayout::standard(epc));
+        }
+
+        let mut columns: Vec<(String, Source)> = vec![];
+        for column in &config.columns {
+            let header = column.header.trim();
+            if header.is_empty() {
+                bail!("Every output column needs a `header`");
+            }
+            if columns.iter().any(|(name, _)| name == header) {
+                bail!("The output column `{}` is listed more than once", header);
+            }
+
+            let source = match (&column.column, column.field, &column.value) {
+                (Some(column), None, None) => Source::Column(column.clone()),
+                (None, Some(field), None) => Source::Field(field),
+                (None, None, Some(value)) => Source::Value(value.clone()),
+                _ => bail!(
+                    "The output column `{}` needs exactly one of `column`, `field` or `value`",
+                    header
+                ),
+            };
+            columns.push((header.to_owned(), source));
+        }
+        let layout = OutputLayout { columns };
+
+        match (layout.uses(OutputField::Epc), epc) {
+            (false, true) => bail!("EPCs are encoded, but the output columns have no `epc` field"),
+            (true, false) => bail!("The output columns have an `epc` field, but EPCs are off"),
+            _ => {}
+        }
+        if !epc && !layout.uses(OutputField::Qty) {
+            bail!("The output columns need a `qty` field, the labels to print of each line");
+        }
+        if grouping != Grouping::Store && layout.store_position().is_none() {
+            bail!(
+                "Files of several stores need a `store` or `store_num` field in the output columns"
+            );
+        }
+
+        Ok(layout)
+    }
+
+    /// Returns the header of each column.
+    pub fn headers(&self) -> Vec<&str> {
+        self.columns
+            .iter()
+            .map(|(header, _)| header.as_str())
+            .collect()
+    }
+
+    /// Returns `true` if a column is filled with `field`.
+    pub fn uses(&self, field: OutputField) -> bool {
+        self.position(field).is_some()
+    }
+
+    /// Returns the index of the first column filled with `field`.
+    pub fn position(&self, field: OutputField) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|(_, source)| *source == Source::Field(field))
+    }
+
+    /// Returns the index of the column holding the store number of each row.
+    pub fn store_position(&self) -> Option<usize> {
+        self.position(OutputField::Store)
+            .or_else(|| self.position(OutputField::StoreNum))
+    }
+
+    /// Fails if a column copies a column missing from the PO file with the given `columns`.
+    pub fn check_columns(&self, columns: &ColumnMap) -> Result<()> {
+        for (header, source) in &self.columns {
+            if let Source::Column(column) = source {
+                if columns.index(column).is_none() {
+                    bail!(
+                        "The PO file has no column `{}` for the output column `{}`",
+                        column,
+                        header
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the row of `record`, a record of a PO file with the given `columns`. `field`
+    /// returns the value of each [OutputField] of the row.
+    pub fn row(
+        &self,
+        record: &StringRecord,
+        columns: &ColumnMap,
+        field: impl Fn(OutputField) -> String,
+    ) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|(_, source)| match source {
+                Source::Column(column) => columns
+                    .index(column)
+                    .and_then(|index| record.get(index))
+                    .unwrap_or_default()
+                    .to_owned(),
+                Source::Field(output_field) => field(*output_field),
+                Source::Value(value) => value.clone(),
+            })
+            .collect()
+    }
+
+    /// Writes `rows`, made with [OutputLayout::row], as CSV under a header row.
+    pub fn write_csv(&self, rows: &[Vec<String>], out: impl Write) -> Result<()> {
+        let mut wtr = csv::Writer::from_writer(out);
+        wtr.write_record(self.headers())?;
+        for row in rows {
+            wtr.write_record(row)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Returns a sheet called `name` of `rows`, made with [OutputLayout::row], with the
+    /// quantities stored as numbers.
+    pub fn sheet(&self, name: &str, rows: &[Vec<String>]) -> Result<SheetWriter> {
+        let mut sheet =
+            SheetWriter::new(name, &self.headers())?.numbers(self.position(OutputField::Qty));
+        for row in rows {
+            sheet.write_row(row)?;
+        }
+        Ok(sheet)
+    }
+}
@@ -0,0 +1,362 @@
+use crate::carton::CartonClass;
+use crate::po::PoNumber;
+use crate::report::boxes;
+use anyhow::Result;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+
+/// Some of the items of a line of a PO, packed in one carton.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackItem {
+    pub upc: String,
+    pub style: String,
+    pub color: String,
+    pub size: String,
+    pub qty: u32,
+}
+
+/// A numbered carton of a store and what goes in it.
+#[derive(Debug, Clone)]
+pub struct Carton {
+    /// The number of the carton within its store, from 1.
+    pub number: usize,
+    pub class: CartonClass,
+    pub items: Vec<PackItem>,
+    /// The SSCC on the ship label of the carton, if one is printed.
+    pub sscc: Option<String>,
+}
+
+impl Carton {
+    /// Returns the number of items in the carton.
+    pub fn qty(&self) -> u32 {
+        self.items.iter().map(|item| item.qty).sum()
+    }
+
+    fn room(&self) -> u32 {
+        self.class.capacity.saturating_sub(self.qty())
+    }
+
+    /// Moves items from the front of `queue` into the carton until it is full, splitting the
+    /// last item if it doesn't fit whole.
+    fn fill(&mut self, queue: &mut VecDeque<PackItem>) {
+        while let Some(item) = queue.front_mut() {
+            let room = self.room();
+            if room == 0 {
+                return;
+            }
+            if item.qty <= room {
+                self.items.extend(queue.pop_front());
+            } else {
+                self.items.push(PackItem {
+                    qty: room,
+                    ..item.clone()
+                });
+                item.qty -= room;
+            }
+        }
+    }
+}
+
+/// A row of a packing list CSV file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PackingRow<'a> {
+    #[serde(rename = "Box")]
+    number: usize,
+    carton: &'a str,
+    upc: &'a str,
+    style: &'a str,
+    color: &'a str,
+    size: &'a str,
+    qty: u32,
+    #[serde(rename = "SSCC", skip_serializing_if = "Option::is_none")]
+    sscc: Option<&'a str>,
+}
+
+/// What goes in each carton shipped to a store.
+#[derive(Debug, Clone)]
+pub struct PackingList {
+    pub po: PoNumber,
+    pub cartons: Vec<Carton>,
+}
+
+impl PackingList {
+    /// Plans the cartons of the store of `po` for `items`, each packed in the carton of its
+    /// class.
+    ///
+    /// Items of the same style and color are kept in the same carton where they fit, without
+    /// using more cartons than the report counts for the store.
+    pub fn plan(po: &PoNumber, items: Vec<(CartonClass, PackItem)>) -> PackingList {
+        // The items of each class grouped by style and color, in the order they are first found.
+        let mut classes: Vec<(CartonClass, Vec<Vec<PackItem>>)> = vec![];
+        for (class, item) in items.into_iter().filter(|(_, item)| item.qty > 0) {
+            let groups = match classes.iter().position(|(c, _)| *c == class) {
+                Some(index) => &mut classes[index].1,
+                None => {
+                    classes.push((class, vec![]));
+                    &mut classes.last_mut().expect("A class was just added").1
+                }
+            };
+            match groups
+                .iter_mut()
+                .find(|group| group[0].style == item.style && group[0].color == item.color)
+            {
+                Some(group) => group.push(item),
+                None => groups.push(vec![item]),
+            }
+        }
+
+        let mut cartons = vec![];
+        for (class, groups) in classes {
+            cartons.extend(pack(&class, groups));
+        }
+        for (index, carton) in cartons.iter_mut().enumerate() {
+            carton.number = index + 1;
+        }
+
+        PackingList {
+            po: po.clone(),
+            cartons,
+        }
+    }
+
+    /// Writes the packing list as CSV, a row for each item of each carton.
+    pub fn write_csv(&self, out: impl Write) -> Result<()> {
+        let mut wtr = csv::Writer::from_writer(out);
+        for carton in &self.cartons {
+            for item in &carton.items {
+                wtr.serialize(PackingRow {
+                    number: carton.number,
+                    carton: &carton.class.name,
+                    upc: &item.upc,
+                    style: &item.style,
+                    color: &item.color,
+                    size: &item.size,
+                    qty: item.qty,
+                    sscc: carton.sscc.as_deref(),
+                })?;
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Writes the packing list as the summary it displays as, see its [fmt::Display].
+    pub fn write_summary(&self, mut out: impl Write) -> Result<()> {
+        out.write_all(self.to_string().as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Returns the numbers of the boxes holding the item with the UPC `upc`, e.g. `1` or `2+3`.
+    pub fn boxes_of(&self, upc: &str) -> String {
+        self.cartons
+            .iter()
+            .filter(|carton| carton.items.iter().any(|item| item.upc == upc))
+            .map(|carton| carton.number.to_string())
+            .collect::<Vec<String>>()
+            .join("+")
+    }
+}
+
+/// Packs the `groups` of items of one class, largest group first, into as many cartons as the
+/// items need.
+fn pack(class: &CartonClass, mut groups: Vec<Vec<PackItem>>) -> Vec<Carton> {
+    let group_qty = |group: &[PackItem]| group.iter().map(|item| item.qty).sum::<u32>();
+    let total: u32 = groups.iter().map(|group| group_qty(group)).sum();
+    let limit = boxes(total, class.capacity) as usize;
+    groups.sort_by_key(|group| Reverse(group_qty(group)));
+
+    let mut cartons: Vec<Carton> = vec![];
+    for group in groups {
+        let mut queue: VecDeque<PackItem> = group.into();
+        while !queue.is_empty() {
+            let needed: u32 = queue.iter().map(|item| item.qty).sum();
+
+            // The fullest carton the rest of the group fits in, else a new carton, else the
+            // carton with the most room, splitting the group.
+            let fits = cartons
+                .iter()
+                .enumerate()
+                .filter(|(_, carton)| carton.room() >= needed)
+                .min_by_key(|(_, carton)| carton.room())
+                .map(|(index, _)| index);
+            let target = match fits {
+                Some(index) => index,
+                None if cartons.len() < limit => {
+                    cartons.push(Carton {
+                        number: 0,
+                        class: class.clone(),
+                        items: vec![],
+                        sscc: None,
+                    });
+                    cartons.len() - 1
+                }
+                None => match cartons
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, carton)| carton.room() > 0)
+                    .max_by_key(|(_, carton)| carton.room())
+                {
+                    Some((index, _)) => index,
+                    // The cartons hold every item, so this is never reached.
+                    None => break,
+                },
+            };
+            cartons[target].fill(&mut queue);
+        }
+    }
+    cartons
+}
+
+/// The printable summary of the packing list.
+impl fmt::Display for PackingList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: u32 = self.cartons.iter().map(|carton| carton.qty()).sum();
+        writeln!(
+            f,
+            "PACKING LIST FOR {} (STORE {})",
+            self.po,
+            self.po.store()
+        )?;
+        writeln!(f, "{} box(es), {} item(s)", self.cartons.len(), total)?;
+
+        for carton in &self.cartons {
+            writeln!(
+                f,
+                "\nBOX {} OF {}: {}, {} of {} item(s)",
+                carton.number,
+                self.cartons.len(),
+                carton.class.name,
+                carton.qty(),
+                carton.class.capacity
+            )?;
+            if let Some(sscc) = &carton.sscc {
+                writeln!(f, "    SSCC {}", sscc)?;
+            }
+            writeln!(
+                f,
+                "    {:<14} {:<12} {:<16} {:<8} {:>5}",
+                "UPC", "STYLE", "COLOR", "SIZE", "QTY"
+            )?;
+            for item in &carton.items {
+                writeln!(
+                    f,
+                    "    {:<14} {:<12} {:<16} {:<8} {:>5}",
+                    item.upc, item.style, item.color, item.size, item.qty
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::po::PoPattern;
+
+    fn po() -> PoNumber {
+        PoPattern::from_delimiter("-").parse("po14423-001").unwrap()
+    }
+
+    fn class(name: &str, capacity: u32) -> CartonClass {
+        CartonClass {
+            name: name.to_owned(),
+            capacity,
+        }
+    }
+
+    fn item(upc: &str, style: &str, color: &str, qty: u32) -> PackItem {
+        PackItem {
+            upc: upc.to_owned(),
+            style: style.to_owned(),
+            color: color.to_owned(),
+            size: "M".to_owned(),
+            qty,
+        }
+    }
+
+    fn qty_of(plan: &PackingList) -> u32 {
+        plan.cartons.iter().map(|carton| carton.qty()).sum()
+    }
+
+    #[test]
+    fn never_uses_more_boxes_than_the_report() {
+        // Groups that each fit a box, but not together, so packing them whole would need more
+        // boxes than the report counts.
+        for qtys in [
+            vec![7, 7, 7],
+            vec![5, 5, 5, 5, 5],
+            vec![9, 1, 9, 1, 9, 1],
+            vec![11, 3, 3, 3],
+        ] {
+            let total: u32 = qtys.iter().sum();
+            let items = qtys
+                .iter()
+                .enumerate()
+                .map(|(i, qty)| {
+                    (
+                        class("Standard", 10),
+                        item("1", &format!("S{}", i), "", *qty),
+                    )
+                })
+                .collect();
+
+            let plan = PackingList::plan(&po(), items);
+            assert!(plan.cartons.len() as u32 <= boxes(total, 10), "{:?}", qtys);
+            assert_eq!(qty_of(&plan), total);
+            assert!(plan.cartons.iter().all(|carton| carton.qty() <= 10));
+        }
+    }
+
+    #[test]
+    fn keeps_a_style_and_color_together() {
+        let standard = class("Standard", 12);
+        let plan = PackingList::plan(
+            &po(),
+            vec![
+                (standard.clone(), item("1", "TS1", "Black", 4)),
+                (standard.clone(), item("2", "TS2", "White", 6)),
+                (standard.clone(), item("3", "TS1", "Black", 4)),
+                (standard.clone(), item("4", "TS3", "Red", 0)),
+                (class("Footwear", 6), item("5", "SH1", "Black", 2)),
+            ],
+        );
+
+        let numbers: Vec<usize> = plan.cartons.iter().map(|carton| carton.number).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert_eq!(plan.cartons[0].qty(), 8);
+        assert_eq!(plan.boxes_of("1"), "1");
+        assert_eq!(plan.boxes_of("3"), "1");
+        assert_eq!(plan.boxes_of("2"), "2");
+        assert_eq!(plan.boxes_of("5"), "3");
+        // Items with nothing to pack are left out.
+        assert_eq!(plan.boxes_of("4"), "");
+    }
+
+    #[test]
+    fn splits_a_line_too_big_for_one_box() {
+        let plan = PackingList::plan(&po(), vec![(class("Tees", 10), item("1", "TS1", "", 25))]);
+        let qtys: Vec<u32> = plan.cartons.iter().map(|carton| carton.qty()).collect();
+        assert_eq!(qtys, [10, 10, 5]);
+        assert_eq!(plan.boxes_of("1"), "1+2+3");
+    }
+
+    #[test]
+    fn writes_a_row_for_each_item_of_each_box() {
+        let plan = PackingList::plan(
+            &po(),
+            vec![(class("Tees", 10), item("1", "TS1", "Black", 12))],
+        );
+        let mut csv = vec![];
+        plan.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "Box,Carton,Upc,Style,Color,Size,Qty\n1,Tees,1,TS1,Black,M,10\n2,Tees,1,TS1,Black,M,2\n"
+        );
+    }
+}
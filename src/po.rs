@@ -0,0 +1,134 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use std::fmt;
+
+/// A PO number split into the base order number and the number of the store it is for.
+///
+/// For example `po14423-001` is order `po14423` for store `001`. Two PO numbers are equal only
+/// if they are written exactly the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PoNumber {
+    raw: String,
+    base: String,
+    store: String,
+}
+
+impl PoNumber {
+    /// Returns the order number without the store number.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Returns the store number the PO is for.
+    pub fn store(&self) -> &str {
+        &self.store
+    }
+}
+
+impl fmt::Display for PoNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// Splits PO numbers into their base order number and store number.
+///
+/// The pattern is a regular expression with the named groups `base` and `store`, e.g.
+/// `^(?P<base>PO\d+)_(?P<store>\d{3})$`.
+#[derive(Debug, Clone)]
+pub struct PoPattern(Regex);
+
+impl PoPattern {
+    pub fn new(pattern: &str) -> Result<PoPattern> {
+        let regex =
+            Regex::new(pattern).with_context(|| format!("Invalid PO pattern `{}`", pattern))?;
+
+        for group in ["base", "store"] {
+            if !regex.capture_names().any(|name| name == Some(group)) {
+                bail!(
+                    "The PO pattern `{}` is missing the named group `(?P<{}>...)`",
+                    pattern,
+                    group
+                );
+            }
+        }
+
+        Ok(PoPattern(regex))
+    }
+
+    /// The pattern of a PO whose store number follows the last `delimiter`, e.g. `po14423-001`.
+    pub fn from_delimiter(delimiter: &str) -> PoPattern {
+        let pattern = format!(r"^(?P<base>.+){}(?P<store>\d+)$", regex::escape(delimiter));
+
+        PoPattern(Regex::new(&pattern).expect("The delimiter is escaped"))
+    }
+
+    /// Parses `po`, failing if it doesn't match the pattern.
+    pub fn parse(&self, po: &str) -> Result<PoNumber> {
+        let captures = self.0.captures(po).ok_or_else(|| {
+            anyhow!(
+                "The PO `{}` does not match the PO pattern `{}`",
+                po,
+                self.0.as_str()
+            )
+        })?;
+
+        Ok(PoNumber {
+            raw: po.to_owned(),
+            base: captures["base"].to_owned(),
+            store: captures["store"].to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_at_the_last_delimiter() {
+        let po = PoPattern::from_delimiter("-")
+            .parse("po14423-2-001")
+            .unwrap();
+        assert_eq!(po.base(), "po14423-2");
+        assert_eq!(po.store(), "001");
+        assert_eq!(po.to_string(), "po14423-2-001");
+    }
+
+    #[test]
+    fn keeps_every_digit_of_the_store() {
+        // Store `0015` must not be taken for store `001`, as a match on the text would.
+        let pattern = PoPattern::from_delimiter("-");
+        assert_eq!(pattern.parse("po14423-0015").unwrap().store(), "0015");
+        assert_ne!(
+            pattern.parse("po14423-0015").unwrap(),
+            pattern.parse("po14423-001").unwrap()
+        );
+    }
+
+    #[test]
+    fn escapes_the_delimiter() {
+        let pattern = PoPattern::from_delimiter(".");
+        assert_eq!(pattern.parse("po14423.001").unwrap().store(), "001");
+        assert!(pattern.parse("po14423x001").is_err());
+    }
+
+    #[test]
+    fn parses_with_named_groups() {
+        let pattern = PoPattern::new(r"^(?P<base>PO\d+)_(?P<store>\d{3})$").unwrap();
+        let po = pattern.parse("PO14423_001").unwrap();
+        assert_eq!((po.base(), po.store()), ("PO14423", "001"));
+
+        let error = pattern.parse("TOTAL").unwrap_err().to_string();
+        assert!(error.contains("The PO `TOTAL` does not match the PO pattern"));
+    }
+
+    #[test]
+    fn requires_both_named_groups() {
+        let error = PoPattern::new(r"^(?P<base>PO\d+)_\d{3}$").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("missing the named group `(?P<store>...)`"));
+        assert!(PoPattern::new("(").is_err());
+    }
+}
@@ -0,0 +1,659 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How long to wait on a printer before giving up on an attempt.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The LPR jobs sent so far by this process, so each gets a job number of its own.
+static JOBS_SENT: AtomicU32 = AtomicU32::new(0);
+
+/// Settings of the label printers LISA prints to directly, e.g.
+///
+/// ```toml
+/// [print]
+/// log = "lisa_print.log"
+///
+/// [[print.printer]]
+/// name = "Dock 1"
+/// host = "10.0.4.21"
+///
+/// [[print.printer]]
+/// name = "Office"
+/// host = "printserver"
+/// protocol = "lpr"
+/// queue = "zebra"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrintConfig {
+    /// The file every print job is logged to, along with whether it was printed.
+    pub log: Option<PathBuf>,
+    #[serde(rename = "printer")]
+    pub printers: Vec<Printer>,
+}
+
+impl PrintConfig {
+    /// Returns the printer called `name`.
+    pub fn printer(&self, name: &str) -> Result<&Printer> {
+        self.printers
+            .iter()
+            .find(|printer| printer.name == name)
+            .ok_or_else(|| anyhow!("There is no printer called '{}' in the config", name))
+    }
+}
+
+/// How a job is sent to a printer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// The file is sent as is over a TCP connection, port 9100 by default.
+    #[default]
+    Raw,
+    /// The file is sent to a print queue of an LPR/LPD server, port 515 by default.
+    Lpr,
+}
+
+/// The label files a printer is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    /// The `.zpl` files of the split, for Zebra printers.
+    #[default]
+    Zpl,
+    /// The `.csv` files of the split, for printers driven by a stored label format.
+    Csv,
+}
+
+impl LabelFormat {
+    /// Returns the extension of the files in this format.
+    pub fn extension(&self) -> &str {
+        match self {
+            LabelFormat::Zpl => "zpl",
+            LabelFormat::Csv => "csv",
+        }
+    }
+
+    /// Returns `true` if `path` is a file in this format.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(self.extension()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Printer {
+    pub name: String,
+    pub host: String,
+    /// The port of the printer, the default port of `protocol` when left out.
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// The LPR queue to print to, `lp` when left out.
+    pub queue: Option<String>,
+    #[serde(default)]
+    pub format: LabelFormat,
+    /// How many more times a job is tried after it fails.
+    #[serde(default = "retries")]
+    pub retries: u32,
+    /// The seconds to wait before trying a failed job again.
+    #[serde(default = "retry_delay")]
+    pub retry_delay: u64,
+    /// Whether a job that failed partway is sent again in full, which may print its labels
+    /// twice. Left off, as labels with EPCs must never be printed twice.
+    #[serde(default)]
+    pub retry_partial: bool,
+}
+
+fn retries() -> u32 {
+    3
+}
+
+fn retry_delay() -> u64 {
+    5
+}
+
+impl Printer {
+    /// Sends `data` to the printer as the job called `name`.
+    pub fn send(&self, name: &str, data: &[u8]) -> Result<()> {
+        let port = self.port.unwrap_or(match self.protocol {
+            Protocol::Raw => 9100,
+            Protocol::Lpr => 515,
+        });
+        let address = (self.host.as_str(), port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to find the printer {}:{}", self.host, port))?
+            .next()
+            .ok_or_else(|| anyhow!("Failed to find the printer {}:{}", self.host, port))?;
+
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .with_context(|| format!("Failed to connect to {}", address))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        match self.protocol {
+            Protocol::Raw => send_raw(&mut stream, data)?,
+            Protocol::Lpr => {
+                let queue = self.queue.as_deref().unwrap_or("lp");
+                send_lpr(&mut stream, queue, name, data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The error of a job that failed after some of it was sent. A raw printer prints labels as
+/// they arrive, so the first labels of the job may have printed, and an LPD server may print a
+/// job whose data file it received even if it never acknowledged it.
+#[derive(Debug)]
+pub struct PartlySent {
+    pub sent: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for PartlySent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The job failed after {} of its {} bytes were sent, some labels may have printed",
+            self.sent, self.total
+        )
+    }
+}
+
+/// Sends `data` as is to a raw printer, failing with [PartlySent] once any of it was sent.
+fn send_raw(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    let mut sent = 0;
+    let result = loop {
+        if sent == data.len() {
+            break stream
+                .flush()
+                .and_then(|()| stream.shutdown(Shutdown::Write));
+        }
+        match stream.write(&data[sent..]) {
+            Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => sent += written,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+
+    match result {
+        Err(e) if sent > 0 => Err(anyhow::Error::new(e).context(PartlySent {
+            sent,
+            total: data.len(),
+        })),
+        result => Ok(result?),
+    }
+}
+
+/// Sends `data` to `queue` of an LPD server, as described by RFC 1179, failing with
+/// [PartlySent] once all of the data file was sent.
+fn send_lpr(stream: &mut TcpStream, queue: &str, name: &str, data: &[u8]) -> Result<()> {
+    let host = "lisa";
+    let job = job_number();
+    let data_file = format!("dfA{:03}{}", job, host);
+    // `l` prints the file as is, so ZPL reaches the printer untouched.
+    let control = format!(
+        "H{}\nPlisa\nJ{}\nl{}\nN{}\nU{}\n",
+        host, name, data_file, name, data_file
+    );
+
+    command(stream, &format!("\x02{}\n", queue))?;
+    command(
+        stream,
+        &format!("\x02{} cfA{:03}{}\n", control.len(), job, host),
+    )?;
+    stream.write_all(control.as_bytes())?;
+    command(stream, "\0")?;
+    command(stream, &format!("\x03{} {}\n", data.len(), data_file))?;
+    stream.write_all(data)?;
+    command(stream, "\0").context(PartlySent {
+        sent: data.len(),
+        total: data.len(),
+    })
+}
+
+/// Returns the number of the next LPR job, from 0 to 999. Jobs are numbered on from the process
+/// id, so the jobs of LISA running on two machines are unlikely to share a name on the server.
+fn job_number() -> u32 {
+    let sent = JOBS_SENT.fetch_add(1, Ordering::Relaxed);
+    std::process::id().wrapping_add(sent) % 1000
+}
+
+/// Writes an LPR command and waits for the server to accept it.
+fn command(stream: &mut TcpStream, command: &str) -> Result<()> {
+    stream.write_all(command.as_bytes())?;
+    let mut ack = [0];
+    stream
+        .read_exact(&mut ack)
+        .context("The print server closed the connection")?;
+    if ack[0] != 0 {
+        bail!("The print server refused the job, error code {}", ack[0]);
+    }
+    Ok(())
+}
+
+/// What became of a print job.
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub printer: String,
+    pub path: PathBuf,
+    /// The number of times the job was sent.
+    pub attempts: u32,
+    /// Why the last attempt failed, or `None` if the job was printed.
+    pub error: Option<String>,
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.error {
+            None => write!(
+                f,
+                "Printed {} on '{}' after {} attempt(s)",
+                self.path.display(),
+                self.printer,
+                self.attempts
+            ),
+            Some(error) => write!(
+                f,
+                "Failed to print {} on '{}' after {} attempt(s): {}",
+                self.path.display(),
+                self.printer,
+                self.attempts,
+                error
+            ),
+        }
+    }
+}
+
+/// Sends files to printers, each printer working through its own queue on its own thread so a
+/// slow or offline printer doesn't hold up the others.
+///
+/// Failed jobs are tried again as set up for their printer. Every job is logged, and appended to
+/// the job log when one is configured.
+pub struct Spooler {
+    queues: HashMap<String, Sender<PathBuf>>,
+    workers: Vec<JoinHandle<()>>,
+    statuses: Arc<Mutex<Vec<JobStatus>>>,
+}
+
+impl Spooler {
+    /// Starts a queue for each of the `printers` in `config`.
+    pub fn new(config: &PrintConfig, printers: &[&Printer]) -> Spooler {
+        let statuses = Arc::new(Mutex::new(vec![]));
+        let mut queues = HashMap::new();
+        let mut workers = vec![];
+
+        for printer in printers {
+            let (sender, receiver) = mpsc::channel::<PathBuf>();
+            let printer = (*printer).clone();
+            let log = config.log.clone();
+            let statuses = statuses.clone();
+
+            queues.insert(printer.name.clone(), sender);
+            workers.push(std::thread::spawn(move || {
+                for path in receiver {
+                    let status = print_file(&printer, &path);
+                    log_status(&status, log.as_deref());
+                    if let Ok(mut statuses) = statuses.lock() {
+                        statuses.push(status);
+                    }
+                }
+            }));
+        }
+
+        Spooler {
+            queues,
+            workers,
+            statuses,
+        }
+    }
+
+    /// Adds the file at `path` to the queue of the printer called `printer`.
+    pub fn submit(&self, printer: &str, path: PathBuf) -> Result<()> {
+        let queue = self
+            .queues
+            .get(printer)
+            .ok_or_else(|| anyhow!("The printer '{}' has no queue", printer))?;
+        info!("Queued {} for '{}'", path.display(), printer);
+
+        queue
+            .send(path)
+            .map_err(|_| anyhow!("The queue of the printer '{}' has stopped", printer))
+    }
+
+    /// Waits for every queue to empty and returns what became of each job.
+    pub fn finish(self) -> Vec<JobStatus> {
+        drop(self.queues);
+        for worker in self.workers {
+            if worker.join().is_err() {
+                error!("A print queue stopped unexpectedly");
+            }
+        }
+
+        self.statuses
+            .lock()
+            .map(|statuses| statuses.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Sends the file at `path` to `printer`, trying again as many times as the printer allows.
+///
+/// A job that failed partway isn't tried again, unless the printer allows it, so no label is
+/// printed twice. That is a raw job that failed after any of it was sent, or an LPR job that
+/// failed after its data file was sent, since an LPD server only prints a job once all of it
+/// has arrived.
+fn print_file(printer: &Printer, path: &Path) -> JobStatus {
+    let mut status = JobStatus {
+        printer: printer.name.clone(),
+        path: path.to_owned(),
+        attempts: 0,
+        error: None,
+    };
+
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            status.error = Some(format!("Failed to read the file: {}", e));
+            return status;
+        }
+    };
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+
+    loop {
+        status.attempts += 1;
+        match printer.send(&name, &data) {
+            Ok(()) => {
+                status.error = None;
+                return status;
+            }
+            Err(e) => {
+                warn!(
+                    "Attempt {} to print {} on '{}' failed: {:#}",
+                    status.attempts,
+                    path.display(),
+                    printer.name,
+                    e
+                );
+                status.error = Some(format!("{:#}", e));
+                if e.is::<PartlySent>() && !printer.retry_partial {
+                    warn!(
+                        "{} may have partly printed on '{}', check its labels before printing it again",
+                        path.display(),
+                        printer.name
+                    );
+                    return status;
+                }
+            }
+        }
+
+        if status.attempts > printer.retries {
+            return status;
+        }
+        std::thread::sleep(Duration::from_secs(printer.retry_delay));
+    }
+}
+
+/// Logs `status`, and appends it to the job log at `log` if there is one.
+fn log_status(status: &JobStatus, log: Option<&Path>) {
+    match status.error {
+        None => info!("{}", status),
+        Some(_) => error!("{}", status),
+    }
+
+    let Some(log) = log else {
+        return;
+    };
+    let line = format!("{}\t{}\n", chrono::Local::now().to_rfc3339(), status);
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    if let Err(e) = written {
+        warn!("Failed to write to the print log {}: {}", log.display(), e);
+    }
+}
+
+/// Sends the `files` a printer takes to the printer called `name`, and waits for them to print.
+///
+/// Returns a sentence saying how many were printed, or fails listing the jobs that weren't.
+pub fn print_files(config: &PrintConfig, name: &str, files: &[PathBuf]) -> Result<String> {
+    let printer = config.printer(name)?;
+    let files: Vec<&PathBuf> = files
+        .iter()
+        .filter(|path| printer.format.matches(path))
+        .collect();
+    if files.is_empty() {
+        bail!(
+            "There are no .{} files to print on '{}'",
+            printer.format.extension(),
+            name
+        );
+    }
+
+    let spooler = Spooler::new(config, &[printer]);
+    for path in &files {
+        spooler.submit(name, (*path).clone())?;
+    }
+    let statuses = spooler.finish();
+
+    let failed: Vec<String> = statuses
+        .iter()
+        .filter(|status| status.error.is_some())
+        .map(|status| status.to_string())
+        .collect();
+    if !failed.is_empty() {
+        bail!(
+            "{} of {} file(s) were not printed:\n\t{}",
+            failed.len(),
+            statuses.len(),
+            failed.join("\n\t")
+        );
+    }
+
+    Ok(format!("Printed {} file(s) on '{}'", statuses.len(), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const LABELS: &[u8] = b"^XA^FO50,50^FDLabel^FS^XZ\n^XA^FO50,50^FDLabel^FS^XZ\n";
+
+    fn printer(protocol: Protocol, port: u16) -> Printer {
+        Printer {
+            name: "Test".to_owned(),
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            protocol,
+            queue: Some("zebra".to_owned()),
+            format: LabelFormat::Zpl,
+            retries: 1,
+            retry_delay: 0,
+            retry_partial: false,
+        }
+    }
+
+    fn listen() -> (TcpListener, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, port)
+    }
+
+    fn read_line(stream: &mut TcpStream) -> String {
+        let mut line = vec![];
+        let mut byte = [0];
+        while line.last() != Some(&b'\n') {
+            stream.read_exact(&mut byte).unwrap();
+            line.push(byte[0]);
+        }
+        String::from_utf8(line).unwrap()
+    }
+
+    /// How the LPD server played by [serve_lpr] answers a job.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Lpd {
+        Accepts,
+        /// Refuses the job at its first command.
+        Refuses,
+        /// Closes the connection instead of acknowledging the data file.
+        HangsUp,
+    }
+
+    /// Plays an LPD server for one job, and returns the commands and files received, in order.
+    fn serve_lpr(mut stream: TcpStream, lpd: Lpd) -> Vec<String> {
+        let mut received = vec![read_line(&mut stream)];
+        if lpd == Lpd::Refuses {
+            stream.write_all(&[1]).unwrap();
+            return received;
+        }
+        stream.write_all(&[0]).unwrap();
+
+        // The control file, then the data file, each followed by a zero byte.
+        for file_number in 0..2 {
+            let command = read_line(&mut stream);
+            let length: usize = command[1..].split(' ').next().unwrap().parse().unwrap();
+            stream.write_all(&[0]).unwrap();
+
+            let mut file = vec![0; length + 1];
+            stream.read_exact(&mut file).unwrap();
+            assert_eq!(file.pop(), Some(0));
+            received.push(command);
+            received.push(String::from_utf8(file).unwrap());
+            if lpd == Lpd::HangsUp && file_number == 1 {
+                return received;
+            }
+            stream.write_all(&[0]).unwrap();
+        }
+        received
+    }
+
+    #[test]
+    fn sends_raw_jobs_as_is() {
+        let (listener, port) = listen();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        printer(Protocol::Raw, port)
+            .send("job.zpl", LABELS)
+            .unwrap();
+        assert_eq!(server.join().unwrap(), LABELS);
+    }
+
+    #[test]
+    fn sends_lpr_jobs_as_rfc_1179_describes() {
+        let (listener, port) = listen();
+        let server = thread::spawn(move || serve_lpr(listener.accept().unwrap().0, Lpd::Accepts));
+
+        printer(Protocol::Lpr, port)
+            .send("job.zpl", LABELS)
+            .unwrap();
+        let received = server.join().unwrap();
+
+        let job = &received[1][received[1].find("cfA").unwrap() + 3..][..3];
+        let control = format!(
+            "Hlisa\nPlisa\nJjob.zpl\nldfA{job}lisa\nNjob.zpl\nUdfA{job}lisa\n",
+            job = job
+        );
+        assert_eq!(
+            received,
+            vec![
+                "\x02zebra\n".to_owned(),
+                format!("\x02{} cfA{}lisa\n", control.len(), job),
+                control,
+                format!("\x03{} dfA{}lisa\n", LABELS.len(), job),
+                String::from_utf8(LABELS.to_vec()).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn tries_refused_jobs_again() {
+        let (listener, port) = listen();
+        let server = thread::spawn(move || {
+            let refused = serve_lpr(listener.accept().unwrap().0, Lpd::Refuses);
+            let printed = serve_lpr(listener.accept().unwrap().0, Lpd::Accepts);
+            (refused, printed)
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job.zpl");
+        std::fs::write(&path, LABELS).unwrap();
+
+        let status = print_file(&printer(Protocol::Lpr, port), &path);
+        assert_eq!(status.attempts, 2);
+        assert_eq!(status.error, None);
+
+        let (refused, printed) = server.join().unwrap();
+        assert_eq!(refused, vec!["\x02zebra\n"]);
+        assert_eq!(printed[4].as_bytes(), LABELS);
+    }
+
+    #[test]
+    fn gives_up_on_jobs_refused_every_time() {
+        let (listener, port) = listen();
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                serve_lpr(listener.accept().unwrap().0, Lpd::Refuses);
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job.zpl");
+        std::fs::write(&path, LABELS).unwrap();
+
+        let status = print_file(&printer(Protocol::Lpr, port), &path);
+        server.join().unwrap();
+        assert_eq!(status.attempts, 2);
+        assert!(status
+            .error
+            .unwrap()
+            .contains("refused the job, error code 1"));
+    }
+
+    #[test]
+    fn does_not_retry_lpr_jobs_whose_labels_were_sent() {
+        let (listener, port) = listen();
+        let server = thread::spawn(move || serve_lpr(listener.accept().unwrap().0, Lpd::HangsUp));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job.zpl");
+        std::fs::write(&path, LABELS).unwrap();
+
+        let status = print_file(&printer(Protocol::Lpr, port), &path);
+        let received = server.join().unwrap();
+        assert_eq!(received[4].as_bytes(), LABELS);
+        assert_eq!(status.attempts, 1);
+        assert!(status
+            .error
+            .unwrap()
+            .contains("some labels may have printed"));
+    }
+
+    #[test]
+    fn numbers_each_lpr_job() {
+        assert_ne!(job_number(), job_number());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::carton::{CartonRule, Cartons};
+use crate::columns::{normalize, Aliases, Column, ColumnMap};
+use crate::config::Config;
+use crate::po::PoPattern;
+use crate::rfid::{self, RfidRule, RfidRules};
+use crate::upc_list::PreTaggedUpcs;
+use anyhow::{anyhow, Context, Result};
+use csv::StringRecord;
+use serde::Deserialize;
+
+/// The name of the profile describing the Infinity app export LISA was written for.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The layout of the PO files sent by a retailer.
+///
+/// Profiles are defined in the config file, for example:
+///
+/// ```toml
+/// [[profile]]
+/// name = "acme"
+/// fingerprint = ["Vendor Style", "Ship Window"]
+/// po_delimiter = "_"
+/// po_pattern = '^(?P<base>PO\d+)_(?P<store>\d{3})$'
+/// box_size = 72
+/// department_column = "Dept"
+///
+/// [profile.columns]
+/// po = ["Order #"]
+/// style = ["Vendor Style"]
+///
+/// [[profile.rfid]]
+/// name = "RF styles"
+/// all = [{ column = "style", prefix = "RF" }]
+///
+/// [[profile.carton]]
+/// name = "Footwear"
+/// capacity = 12
+/// departments = ["Footwear"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    /// Header names that only this retailer's files contain, used to pick the profile.
+    #[serde(default)]
+    pub fingerprint: Vec<String>,
+    /// Extra header names accepted for each column.
+    #[serde(default)]
+    pub columns: Aliases,
+    /// The character(s) separating the order number from the store number in a PO.
+    #[serde(default = "default_po_delimiter")]
+    pub po_delimiter: String,
+    /// A regular expression splitting a PO into its `base` and `store` groups, used instead of
+    /// `po_delimiter` when given.
+    #[serde(default)]
+    pub po_pattern: Option<String>,
+    /// The rules deciding which items already have an RFID tag, see [RfidRule].
+    #[serde(default = "rfid::default_rules")]
+    pub rfid: Vec<RfidRule>,
+    /// The number of items that fit in a box, unless one of the `carton` rules picks them.
+    #[serde(default = "default_box_size")]
+    pub box_size: u32,
+    /// The cartons that hold a different number of items than `box_size`, see [CartonRule].
+    #[serde(default, rename = "carton")]
+    pub cartons: Vec<CartonRule>,
+    /// The column carton rules find the department of an item in.
+    #[serde(default = "default_department_column")]
+    pub department_column: Column,
+}
+
+fn default_po_delimiter() -> String {
+    "-".to_owned()
+}
+
+fn default_box_size() -> u32 {
+    60
+}
+
+fn default_department_column() -> Column {
+    Column::Header("department".to_owned())
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            name: DEFAULT_PROFILE.to_owned(),
+            fingerprint: vec![],
+            columns: Aliases::default(),
+            po_delimiter: default_po_delimiter(),
+            po_pattern: None,
+            rfid: rfid::default_rules(),
+            box_size: default_box_size(),
+            cartons: vec![],
+            department_column: default_department_column(),
+        }
+    }
+}
+
+impl Profile {
+    /// Finds the columns of the profile in `headers`. The `[columns]` aliases of the config
+    /// apply to every profile.
+    pub fn resolve(&self, headers: &StringRecord, config: &Config) -> Result<ColumnMap> {
+        ColumnMap::resolve(headers, &self.columns.merged(&config.columns))
+            .with_context(|| format!("The PO file does not fit the '{}' profile", self.name))
+    }
+
+    /// Returns the pattern used to split the POs of the profile's files.
+    pub fn po_pattern(&self) -> Result<PoPattern> {
+        match &self.po_pattern {
+            Some(pattern) => PoPattern::new(pattern)
+                .with_context(|| format!("Invalid `po_pattern` in the '{}' profile", self.name)),
+            None => Ok(PoPattern::from_delimiter(&self.po_delimiter)),
+        }
+    }
+
+    /// Returns the RFID rules of the profile for a PO file with the given `columns`, along with
+    /// the pre-tagged `upcs`.
+    pub fn rfid_rules(&self, columns: &ColumnMap, upcs: PreTaggedUpcs) -> Result<RfidRules> {
+        RfidRules::new(&self.rfid, columns, upcs)
+            .with_context(|| format!("Invalid RFID rules in the '{}' profile", self.name))
+    }
+
+    /// Returns the cartons of the profile for a PO file with the given `columns`.
+    pub fn cartons(&self, columns: &ColumnMap) -> Result<Cartons> {
+        Cartons::new(
+            &self.cartons,
+            self.box_size,
+            columns,
+            &self.department_column,
+        )
+        .with_context(|| format!("Invalid cartons in the '{}' profile", self.name))
+    }
+
+    /// Returns `true` if every fingerprint header of the profile is found in `headers`.
+    fn fits(&self, headers: &[String]) -> bool {
+        self.fingerprint
+            .iter()
+            .all(|name| headers.contains(&normalize(name)))
+    }
+}
+
+/// Picks the profile for a PO file from its header row, or uses the profile called `name`.
+///
+/// A profile matches when all of its fingerprint headers are present and all of its columns
+/// can be found. When several match, the one with the most fingerprint headers wins, so the
+/// default profile, which has none, is only used when no retailer is recognized.
+pub fn select(
+    headers: &StringRecord,
+    config: &Config,
+    name: Option<&str>,
+) -> Result<(Profile, ColumnMap)> {
+    if let Some(name) = name {
+        let profile = config.profile(name)?;
+        let columns = profile.resolve(headers, config)?;
+        return Ok((profile, columns));
+    }
+
+    let normalized: Vec<String> = headers.iter().map(normalize).collect();
+    let mut best: Option<(Profile, ColumnMap)> = None;
+
+    for profile in config.all_profiles() {
+        if !profile.fits(&normalized) {
+            continue;
+        }
+        let columns = match profile.resolve(headers, config) {
+            Ok(columns) => columns,
+            Err(e) => {
+                debug!("Skipping profile: {:#}", e);
+                continue;
+            }
+        };
+
+        let is_better = match &best {
+            Some((current, _)) => profile.fingerprint.len() > current.fingerprint.len(),
+            None => true,
+        };
+        if is_better {
+            best = Some((profile, columns));
+        }
+    }
+
+    match best {
+        Some((profile, columns)) => {
+            info!("Detected the '{}' profile", &profile.name);
+            Ok((profile, columns))
+        }
+        None => {
+            // Resolving the default profile again gives the user the list of missing columns.
+            let profile = config.profile(DEFAULT_PROFILE)?;
+            let error = match profile.resolve(headers, config) {
+                Err(e) => e,
+                Ok(_) => anyhow!("The PO file does not fit the '{}' profile", profile.name),
+            };
+            Err(error.context("No profile matches the header row of the PO file"))
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use crate::output::{OutputField, OutputLayout};
+use crate::po::PoNumber;
+use crate::rfid::Reason;
+use crate::workbook::{self, Sheet};
+use crate::xlsx::{self, SheetWriter};
+use anyhow::{bail, Context, Result};
+use csv::StringRecord;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// The quantities ordered for a single store.
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreTotals {
+    /// The PO of the store, e.g. `po14423-001`.
+    pub po: String,
+    /// The store number parsed from the PO, e.g. `001`.
+    pub store: String,
+    /// The name of the store from the store directory, if it is in one.
+    pub name: Option<String>,
+    /// The city of the store from the store directory, if it is in one.
+    pub city: Option<String>,
+    pub total: u32,
+    /// Items that need an RFID label printed, the quantity written to the store's file.
+    pub labels_to_print: u32,
+    /// Items thought to already have an RFID tag applied from the factory.
+    pub pre_tagged: u32,
+    pub boxes: u32,
+}
+
+impl StoreTotals {
+    /// An empty entry for the store of `po`.
+    pub fn new(po: &PoNumber) -> StoreTotals {
+        StoreTotals {
+            po: po.to_string(),
+            store: po.store().to_owned(),
+            name: None,
+            city: None,
+            total: 0,
+            labels_to_print: 0,
+            pre_tagged: 0,
+            boxes: 0,
+        }
+    }
+
+    /// Returns the name and city of the store, e.g. `Downtown, Springfield`, or an empty string
+    /// if it isn't in the store directory.
+    pub fn location(&self) -> String {
+        [&self.name, &self.city]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// A line of a PO whose quantity is set to `0` in the split files, because its item is thought
+/// to already have an RFID tag.
+#[derive(Debug, Clone, Serialize)]
+pub struct PreTaggedLine {
+    /// The line of the PO file.
+    pub line: u64,
+    pub po: String,
+    pub upc: String,
+    pub qty: u32,
+    pub reason: Reason,
+}
+
+/// Returns a sentence counting the `lines` zeroed because of a UPC list and because of an RFID
+/// rule, e.g. `3 line(s) zeroed by a UPC list, 5 by an RFID rule`.
+pub fn zeroed_summary(lines: &[PreTaggedLine]) -> String {
+    let by_list = lines
+        .iter()
+        .filter(|line| matches!(line.reason, Reason::UpcList(_)))
+        .count();
+
+    format!(
+        "{} line(s) zeroed by a UPC list, {} by an RFID rule",
+        by_list,
+        lines.len() - by_list
+    )
+}
+
+/// The cartons of one kind needed for an order.
+#[derive(Debug, Clone, Serialize)]
+pub struct CartonTotals {
+    /// The name of the carton, see [crate::carton::CartonRule].
+    pub carton: String,
+    /// The number of items that fit in the carton.
+    pub capacity: u32,
+    /// The number of items packed in the carton.
+    pub qty: u32,
+    /// The cartons needed, counted for each store on its own.
+    pub boxes: u32,
+}
+
+/// A report of the stores in a PO and the number of items ordered for each.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub num_stores: u32,
+    /// The number of items ordered.
+    pub total: u32,
+    pub labels_to_print: u32,
+    pub pre_tagged: u32,
+    /// The cartons needed for every store, the sum of `cartons`.
+    pub boxes: u32,
+    /// The breakdown of the boxes by carton, sorted by name.
+    pub cartons: Vec<CartonTotals>,
+    /// The breakdown of the order by store, sorted by store number.
+    pub stores: Vec<StoreTotals>,
+    /// The lines of the order that need no label, in the order of the PO file.
+    pub pre_tagged_lines: Vec<PreTaggedLine>,
+    /// The stores of the order missing from the store directory, sorted by number.
+    pub unknown_stores: Vec<String>,
+}
+
+/// Returns the number of boxes needed to pack `qty` items, `box_size` to a box.
+pub fn boxes(qty: u32, box_size: u32) -> u32 {
+    qty.div_ceil(box_size.max(1))
+}
+
+impl Report {
+    /// Builds the report of an order from the totals of each of its stores and of each carton,
+    /// its pre-tagged lines and the stores missing from the store directory.
+    pub fn new(
+        mut stores: Vec<StoreTotals>,
+        mut cartons: Vec<CartonTotals>,
+        pre_tagged_lines: Vec<PreTaggedLine>,
+        mut unknown_stores: Vec<String>,
+    ) -> Report {
+        stores.sort_by(|a, b| a.store.cmp(&b.store).then_with(|| a.po.cmp(&b.po)));
+        cartons.sort_by(|a, b| a.carton.cmp(&b.carton));
+        unknown_stores.sort();
+
+        let labels_to_print = stores.iter().map(|store| store.labels_to_print).sum();
+        let pre_tagged = stores.iter().map(|store| store.pre_tagged).sum();
+        let total = stores.iter().map(|store| store.total).sum();
+
+        Report {
+            num_stores: stores.len() as u32,
+            total,
+            labels_to_print,
+            pre_tagged,
+            boxes: cartons.iter().map(|carton| carton.boxes).sum(),
+            cartons,
+            stores,
+            pre_tagged_lines,
+            unknown_stores,
+        }
+    }
+}
+
+/// Returns a sentence listing the stores missing from the store directory, e.g.
+/// `2 store(s) are not in the store directory: 045, 101`.
+pub fn unknown_summary(stores: &[String]) -> String {
+    format!(
+        "{} store(s) are not in the store directory: {}",
+        stores.len(),
+        stores.join(", ")
+    )
+}
+
+/// The formats a report can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum ReportFormat {
+    /// A table for people to read.
+    Table,
+    Json,
+    /// One row per store followed by a row with the totals of the order.
+    Csv,
+    /// A workbook with the rows of `csv` in a sheet, the totals in bold.
+    Xlsx,
+}
+
+impl ReportFormat {
+    /// Returns the extension of a file of a report in this format.
+    pub fn extension(&self) -> &str {
+        match self {
+            ReportFormat::Table => "txt",
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// The columns of the rows of a report written as CSV or XLSX.
+const COLUMNS: [&str; 8] = [
+    "po",
+    "store",
+    "name",
+    "city",
+    "total",
+    "labels_to_print",
+    "pre_tagged",
+    "boxes",
+];
+
+impl Report {
+    /// Returns the report written in `format`, the contents of a file.
+    pub fn render(&self, format: ReportFormat) -> Result<Vec<u8>> {
+        match format {
+            ReportFormat::Table => Ok(self.to_string().into_bytes()),
+            ReportFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ReportFormat::Csv => self.csv(),
+            ReportFormat::Xlsx => self.xlsx(),
+        }
+    }
+
+    fn csv(&self) -> Result<Vec<u8>> {
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(vec![]);
+
+        wtr.write_record(COLUMNS)?;
+        for store in &self.stores {
+            wtr.serialize(store)?;
+        }
+        wtr.write_record(self.totals_row())?;
+
+        Ok(wtr.into_inner()?)
+    }
+
+    fn xlsx(&self) -> Result<Vec<u8>> {
+        // The counts, from `total` on, are stored as numbers.
+        let mut sheet = SheetWriter::new("Report", &COLUMNS)?.numbers(4..COLUMNS.len());
+        for store in &self.stores {
+            sheet.write_row(&[
+                store.po.clone(),
+                store.store.clone(),
+                store.name.clone().unwrap_or_default(),
+                store.city.clone().unwrap_or_default(),
+                store.total.to_string(),
+                store.labels_to_print.to_string(),
+                store.pre_tagged.to_string(),
+                store.boxes.to_string(),
+            ])?;
+        }
+        sheet.write_totals(&self.totals_row())?;
+
+        xlsx::to_bytes(vec![sheet])
+    }
+
+    /// Returns the row of the totals of the order, under the row of each store.
+    fn totals_row(&self) -> [String; 8] {
+        [
+            "TOTAL".to_owned(),
+            "".to_owned(),
+            "".to_owned(),
+            "".to_owned(),
+            self.total.to_string(),
+            self.labels_to_print.to_string(),
+            self.pre_tagged.to_string(),
+            self.boxes.to_string(),
+        ]
+    }
+
+    /// Reads back the CSV and XLSX `files` written for each PO, with the sheet of a workbook
+    /// holding the lines, in the columns of `layout`, and checks that the quantities in them add
+    /// up to the labels to print of the report, so the report can be trusted to describe the
+    /// split files.
+    pub fn reconcile(
+        &self,
+        files: &[(&[PoNumber], &Path, Option<&str>)],
+        layout: &OutputLayout,
+    ) -> Result<()> {
+        let qty_index = layout.position(OutputField::Qty);
+        let epc_index = layout.position(OutputField::Epc);
+        let store_index = layout.store_position();
+        let mut written: HashMap<String, u32> = HashMap::new();
+        for (pos, path, sheet) in files {
+            for po in pos.iter() {
+                written.entry(po.to_string()).or_default();
+            }
+            let rows: Vec<StringRecord> = match workbook::is_workbook(path) {
+                // The header is the first row of each sheet written.
+                true => Sheet::open(path, *sheet)?.records(0, None),
+                false => csv::Reader::from_path(path)
+                    .and_then(|mut rdr| rdr.records().collect())
+                    .with_context(|| format!("Failed to read back {}", path.display()))?,
+            };
+            for row in rows {
+                // The lines of a file of several stores are told apart by their store number.
+                let po = match pos {
+                    [po] => po,
+                    _ => {
+                        let store = store_index
+                            .and_then(|index| row.get(index))
+                            .unwrap_or_default();
+                        pos.iter().find(|po| po.store() == store).with_context(|| {
+                            format!(
+                                "{} has a line for store `{}`, which is not one of its stores",
+                                path.display(),
+                                store
+                            )
+                        })?
+                    }
+                };
+                // Without a qty column each row with an EPC is a label. The row of a line
+                // without labels has no EPC.
+                let qty = match (qty_index, epc_index) {
+                    (Some(index), _) => row.get(index).unwrap_or_default().parse::<u32>()?,
+                    (None, Some(index)) => !row.get(index).unwrap_or_default().is_empty() as u32,
+                    (None, None) => 1,
+                };
+                *written.entry(po.to_string()).or_default() += qty;
+            }
+        }
+
+        let mut mismatches = vec![];
+        for store in &self.stores {
+            let qty = written.remove(&store.po).unwrap_or_default();
+            if qty != store.labels_to_print {
+                mismatches.push(format!(
+                    "{}: the report has {} label(s) to print, the file has {}",
+                    store.po, store.labels_to_print, qty
+                ));
+            }
+        }
+        for (po, qty) in written {
+            mismatches.push(format!(
+                "{}: the file has {} label(s), but the PO is not in the report",
+                po, qty
+            ));
+        }
+
+        if !mismatches.is_empty() {
+            bail!(
+                "The files written do not match the report:\n\t{}",
+                mismatches.join("\n\t")
+            );
+        }
+
+        info!(
+            "Reconciled {} label(s) to print across {} file(s)",
+            self.labels_to_print,
+            files.len()
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<20} {:>5} {:<30} {:>7} {:>15} {:>10} {:>6}",
+            "PO", "STORE", "NAME", "TOTAL", "LABELS TO PRINT", "PRE-TAGGED", "BOXES"
+        )?;
+
+        for store in &self.stores {
+            writeln!(
+                f,
+                "{:<20} {:>5} {:<30} {:>7} {:>15} {:>10} {:>6}",
+                store.po,
+                store.store,
+                store.location(),
+                store.total,
+                store.labels_to_print,
+                store.pre_tagged,
+                store.boxes
+            )?;
+        }
+
+        writeln!(
+            f,
+            "\nTOTALS FOR THIS ORDER:
+        TOTAL STORES: {}
+        TOTAL ITEMS: {}
+        NEEDS RFID PRINTED: {}
+        MAY NOT NEED RFID: {}
+        TOTAL BOXES: {}",
+            self.num_stores, self.total, self.labels_to_print, self.pre_tagged, self.boxes
+        )?;
+        for carton in &self.cartons {
+            writeln!(
+                f,
+                "            {} ({} per box): {} box(es) for {} item(s)",
+                carton.carton, carton.capacity, carton.boxes, carton.qty
+            )?;
+        }
+
+        if !self.unknown_stores.is_empty() {
+            writeln!(
+                f,
+                "\nUNKNOWN STORES: {}",
+                unknown_summary(&self.unknown_stores)
+            )?;
+        }
+
+        if self.pre_tagged_lines.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(
+            f,
+            "\nPRE-TAGGED LINES: {}",
+            zeroed_summary(&self.pre_tagged_lines)
+        )?;
+        writeln!(
+            f,
+            "{:>6} {:<20} {:<14} {:>5} REASON",
+            "LINE", "PO", "UPC", "QTY"
+        )?;
+        for line in &self.pre_tagged_lines {
+            writeln!(
+                f,
+                "{:>6} {:<20} {:<14} {:>5} {}",
+                line.line, line.po, line.upc, line.qty, line.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouping::Grouping;
+    use crate::output::OutputConfig;
+    use crate::po::PoPattern;
+
+    fn report(labels: &[(&PoNumber, u32)]) -> Report {
+        let stores = labels
+            .iter()
+            .map(|(po, labels_to_print)| StoreTotals {
+                total: *labels_to_print,
+                labels_to_print: *labels_to_print,
+                ..StoreTotals::new(po)
+            })
+            .collect();
+        Report::new(stores, vec![], vec![], vec![])
+    }
+
+    /// Writes a CSV file of the standard layout with a row for each store and qty of `rows`.
+    fn write(dir: &Path, name: &str, rows: &[(&str, u32)]) -> std::path::PathBuf {
+        let mut text =
+            "Po,StyleCode,ColorCode,MsrpSize,StyleDesc,ColorDesc,Upc,StoreNum,Qty\n".to_owned();
+        for (store, qty) in rows {
+            text += &format!(
+                "po1-{},TEE1,BLK,M,Tee,Black,614141000012,{},{}\n",
+                store, store, qty
+            );
+        }
+        let path = dir.join(name);
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn reconciles_files_of_several_stores() {
+        let pattern = PoPattern::from_delimiter("-");
+        let pos = [
+            pattern.parse("po1-001").unwrap(),
+            pattern.parse("po1-002").unwrap(),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "po1.csv", &[("001", 2), ("002", 1), ("001", 1)]);
+        let layout = OutputLayout::standard(false);
+        let files = [(&pos[..], path.as_path(), None)];
+
+        let report = report(&[(&pos[0], 3), (&pos[1], 1)]);
+        report.reconcile(&files, &layout).unwrap();
+    }
+
+    #[test]
+    fn reports_every_mismatch() {
+        let pattern = PoPattern::from_delimiter("-");
+        let pos = [
+            pattern.parse("po1-001").unwrap(),
+            pattern.parse("po1-002").unwrap(),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let first = write(dir.path(), "po1-001.csv", &[("001", 2)]);
+        let second = write(dir.path(), "po1-002.csv", &[("002", 4)]);
+        let layout = OutputLayout::standard(false);
+        let files = [
+            (&pos[..1], first.as_path(), None),
+            (&pos[1..], second.as_path(), None),
+        ];
+
+        let error = report(&[(&pos[0], 3)])
+            .reconcile(&files, &layout)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The files written do not match the report:\n\t\
+             po1-001: the report has 3 label(s) to print, the file has 2\n\t\
+             po1-002: the file has 4 label(s), but the PO is not in the report"
+        );
+    }
+
+    #[test]
+    fn counts_a_label_for_each_epc_without_a_qty_column() {
+        let pattern = PoPattern::from_delimiter("-");
+        let pos = [pattern.parse("po1-001").unwrap()];
+        let config: OutputConfig = toml::from_str(
+            "[[column]]\nheader = \"Upc\"\ncolumn = \"upc\"\n\
+             [[column]]\nheader = \"Epc\"\nfield = \"epc\"\n",
+        )
+        .unwrap();
+        let layout = OutputLayout::new(&config, true, Grouping::Store).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("po1-001.csv");
+        // The second line is pre-tagged, so it has a row but no EPC.
+        std::fs::write(
+            &path,
+            "Upc,Epc\n614141000012,3074257BF7194E4000001A85\n\
+             614141000012,3074257BF7194E4000001A86\n614141000029,\n",
+        )
+        .unwrap();
+        let files = [(&pos[..], path.as_path(), None)];
+
+        report(&[(&pos[0], 2)]).reconcile(&files, &layout).unwrap();
+        let error = report(&[(&pos[0], 3)]).reconcile(&files, &layout);
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn rejects_lines_of_other_stores() {
+        let pattern = PoPattern::from_delimiter("-");
+        let pos = [
+            pattern.parse("po1-001").unwrap(),
+            pattern.parse("po1-002").unwrap(),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "po1.csv", &[("001", 1), ("003", 1)]);
+        let files = [(&pos[..], path.as_path(), None)];
+
+        let report = report(&[(&pos[0], 1), (&pos[1], 0)]);
+        let error = report
+            .reconcile(&files, &OutputLayout::standard(false))
+            .unwrap_err();
+        assert!(error.to_string().contains("a line for store `003`"));
+    }
+}
@@ -0,0 +1,408 @@
+use crate::columns::{Column, ColumnMap, Field};
+use crate::upc_list::PreTaggedUpcs;
+use anyhow::{anyhow, bail, Context, Result};
+use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A rule deciding whether items are thought to already have an RFID tag from the factory.
+///
+/// A rule fires when every condition in `all` matches and, if `any` isn't empty, at least one
+/// condition in `any` matches. Rules are defined for each profile, for example:
+///
+/// ```toml
+/// [[profile.rfid]]
+/// name = "ACME ships tagged"
+/// priority = 10
+/// all = [{ column = "Vendor", equals = "ACME" }]
+/// any = [{ column = "upc", prefix = "0614141" }, { column = "style", regex = '^RF\d+$' }]
+///
+/// [[profile.rfid]]
+/// name = "Samples are never tagged"
+/// priority = 20
+/// pre_tagged = false
+/// all = [{ column = "style_desc", contains = "SAMPLE" }]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RfidRule {
+    /// The name of the rule, used to explain why an item was classified the way it was.
+    pub name: String,
+    /// Rules with a higher priority are tried first. Rules of the same priority are tried in
+    /// the order they are written.
+    #[serde(default)]
+    pub priority: i32,
+    /// What the rule decides when it fires, so a rule can also mark items as needing a label.
+    #[serde(default = "pre_tagged")]
+    pub pre_tagged: bool,
+    #[serde(default)]
+    pub all: Vec<Condition>,
+    #[serde(default)]
+    pub any: Vec<Condition>,
+}
+
+fn pre_tagged() -> bool {
+    true
+}
+
+/// A test of the value of one column of a record. Exactly one of the tests must be given.
+///
+/// Values are compared after trimming surrounding whitespace, and are case sensitive.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Condition {
+    pub column: Option<Column>,
+    pub equals: Option<String>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub contains: Option<String>,
+    pub regex: Option<String>,
+}
+
+/// The rules LISA has always used: items whose style description contains a `$` are tagged.
+pub fn default_rules() -> Vec<RfidRule> {
+    vec![RfidRule {
+        name: "$ in the style description".to_owned(),
+        priority: 0,
+        pre_tagged: true,
+        all: vec![Condition {
+            column: Some(Column::Field(Field::StyleDesc)),
+            contains: Some("$".to_owned()),
+            ..Default::default()
+        }],
+        any: vec![],
+    }]
+}
+
+/// Why an item was classified the way it was.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "source", content = "name", rename_all = "snake_case")]
+pub enum Reason {
+    /// The UPC of the item is on the pre-tagged UPC list with this name.
+    UpcList(String),
+    /// The RFID rule with this name fired.
+    RfidRule(String),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::UpcList(name) => write!(f, "UPC list '{}'", name),
+            Reason::RfidRule(name) => write!(f, "RFID rule '{}'", name),
+        }
+    }
+}
+
+/// How an item was classified, and why.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Classification {
+    /// `true` if the item is thought to already have an RFID tag, so no label is printed.
+    pub pre_tagged: bool,
+    /// What decided, or `None` if nothing did and the item needs a label.
+    pub reason: Option<Reason>,
+}
+
+#[derive(Debug)]
+enum Test {
+    Equals(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    Regex(Regex),
+}
+
+/// A condition bound to the position of its column in the PO file.
+#[derive(Debug)]
+struct Matcher {
+    index: usize,
+    test: Test,
+}
+
+impl Matcher {
+    fn new(condition: &Condition, columns: &ColumnMap) -> Result<Matcher> {
+        let column = condition
+            .column
+            .as_ref()
+            .ok_or_else(|| anyhow!("A condition is missing its `column`"))?;
+        let index = columns
+            .index(column)
+            .ok_or_else(|| anyhow!("The PO file has no column `{}`", column))?;
+
+        let mut tests = vec![];
+        if let Some(value) = &condition.equals {
+            tests.push(Test::Equals(value.clone()));
+        }
+        if let Some(value) = &condition.prefix {
+            tests.push(Test::Prefix(value.clone()));
+        }
+        if let Some(value) = &condition.suffix {
+            tests.push(Test::Suffix(value.clone()));
+        }
+        if let Some(value) = &condition.contains {
+            tests.push(Test::Contains(value.clone()));
+        }
+        if let Some(pattern) = &condition.regex {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("Invalid regex `{}` for column `{}`", pattern, column))?;
+            tests.push(Test::Regex(regex));
+        }
+
+        if tests.len() != 1 {
+            bail!(
+                "The condition on column `{}` must have exactly one of `equals`, `prefix`, `suffix`, `contains` or `regex`",
+                column
+            );
+        }
+
+        Ok(Matcher {
+            index,
+            test: tests.remove(0),
+        })
+    }
+
+    fn matches(&self, record: &StringRecord) -> bool {
+        let value = record.get(self.index).unwrap_or_default().trim();
+
+        match &self.test {
+            Test::Equals(expected) => value == expected,
+            Test::Prefix(prefix) => value.starts_with(prefix.as_str()),
+            Test::Suffix(suffix) => value.ends_with(suffix.as_str()),
+            Test::Contains(part) => value.contains(part.as_str()),
+            Test::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    name: String,
+    pre_tagged: bool,
+    all: Vec<Matcher>,
+    any: Vec<Matcher>,
+}
+
+impl CompiledRule {
+    fn matches(&self, record: &StringRecord) -> bool {
+        self.all.iter().all(|matcher| matcher.matches(record))
+            && (self.any.is_empty() || self.any.iter().any(|matcher| matcher.matches(record)))
+    }
+}
+
+/// The RFID rules of a profile, bound to the columns of a PO file, along with the pre-tagged
+/// UPC lists in effect, which decide for the items no rule fires for.
+///
+/// Splitting a PO and reporting on it classify items with the same `RfidRules`, so their counts
+/// agree.
+#[derive(Debug)]
+pub struct RfidRules {
+    upcs: PreTaggedUpcs,
+    upc_index: Option<usize>,
+    rules: Vec<CompiledRule>,
+}
+
+impl RfidRules {
+    /// Compiles `rules` for a PO file with the given `columns`, failing if a rule names a
+    /// column the file doesn't have.
+    pub fn new(rules: &[RfidRule], columns: &ColumnMap, upcs: PreTaggedUpcs) -> Result<RfidRules> {
+        let mut compiled = vec![];
+
+        for rule in rules {
+            let compile = |conditions: &[Condition]| -> Result<Vec<Matcher>> {
+                conditions
+                    .iter()
+                    .map(|condition| Matcher::new(condition, columns))
+                    .collect()
+            };
+            let rule_error = || format!("Invalid RFID rule '{}'", rule.name);
+            let all = compile(&rule.all).with_context(rule_error)?;
+            let any = compile(&rule.any).with_context(rule_error)?;
+
+            if all.is_empty() && any.is_empty() {
+                bail!("The RFID rule '{}' has no conditions", rule.name);
+            }
+
+            compiled.push((
+                rule.priority,
+                CompiledRule {
+                    name: rule.name.clone(),
+                    pre_tagged: rule.pre_tagged,
+                    all,
+                    any,
+                },
+            ));
+        }
+
+        // A stable sort keeps rules of the same priority in the order they were written.
+        compiled.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        Ok(RfidRules {
+            upcs,
+            upc_index: columns.index(&Column::Field(Field::Upc)),
+            rules: compiled.into_iter().map(|(_, rule)| rule).collect(),
+        })
+    }
+
+    /// Classifies `record`. The first rule that fires decides, so a rule can also mark items on
+    /// a UPC list as needing a label. Otherwise items whose UPC is on a pre-tagged UPC list are
+    /// pre-tagged, and items nothing fires for need a label.
+    pub fn classify(&self, record: &StringRecord) -> Classification {
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(record)) {
+            return Classification {
+                pre_tagged: rule.pre_tagged,
+                reason: Some(Reason::RfidRule(rule.name.clone())),
+            };
+        }
+
+        let upc = self.upc_index.and_then(|index| record.get(index));
+        match upc.and_then(|upc| self.upcs.find(upc)) {
+            Some(list) => Classification {
+                pre_tagged: true,
+                reason: Some(Reason::UpcList(list.to_owned())),
+            },
+            None => Classification::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::Aliases;
+    use crate::upc_list::UpcList;
+    use chrono::NaiveDate;
+
+    const HEADERS: [&str; 9] = [
+        "PO",
+        "Style",
+        "Color Code",
+        "Size",
+        "Style Desc",
+        "Color Desc",
+        "UPC",
+        "Qty",
+        "Vendor",
+    ];
+
+    #[derive(Deserialize)]
+    struct Profile {
+        rfid: Vec<RfidRule>,
+    }
+
+    fn columns() -> ColumnMap {
+        ColumnMap::resolve(&StringRecord::from(HEADERS.to_vec()), &Aliases::default()).unwrap()
+    }
+
+    fn rules(toml: &str, upcs: PreTaggedUpcs) -> Result<RfidRules> {
+        let profile: Profile = toml::from_str(toml).unwrap();
+        RfidRules::new(&profile.rfid, &columns(), upcs)
+    }
+
+    fn record(style: &str, style_desc: &str, upc: &str, vendor: &str) -> StringRecord {
+        StringRecord::from(vec![
+            "po1-001", style, "001", "M", style_desc, "Black", upc, "3", vendor,
+        ])
+    }
+
+    fn rule_fired(name: &str, pre_tagged: bool) -> Classification {
+        Classification {
+            pre_tagged,
+            reason: Some(Reason::RfidRule(name.to_owned())),
+        }
+    }
+
+    const ACME: &str = r#"
+[[rfid]]
+name = "ACME ships tagged"
+priority = 10
+all = [{ column = "Vendor", equals = "ACME" }]
+any = [{ column = "upc", prefix = "0614141" }, { column = "style", regex = '^RF\d+$' }]
+
+[[rfid]]
+name = "Samples are never tagged"
+priority = 20
+pre_tagged = false
+all = [{ column = "style_desc", contains = "SAMPLE" }]
+"#;
+
+    #[test]
+    fn tags_items_with_a_dollar_by_default() {
+        let rules = RfidRules::new(&default_rules(), &columns(), Default::default()).unwrap();
+        let tagged = rules.classify(&record("A1", "TEE $", "000000000001", ""));
+        assert_eq!(tagged, rule_fired("$ in the style description", true));
+        assert_eq!(
+            rules.classify(&record("A1", "TEE", "000000000001", "")),
+            Classification::default()
+        );
+    }
+
+    #[test]
+    fn fires_when_all_and_any_match() {
+        let rules = rules(ACME, Default::default()).unwrap();
+        let acme = rule_fired("ACME ships tagged", true);
+        assert_eq!(rules.classify(&record("RF12", "TEE", "1", " ACME ")), acme);
+        assert_eq!(
+            rules.classify(&record("A1", "TEE", "061414100001", "ACME")),
+            acme
+        );
+        assert_eq!(
+            rules.classify(&record("A1", "TEE", "1", "ACME")),
+            Classification::default()
+        );
+        assert_eq!(
+            rules.classify(&record("RF12", "TEE", "1", "Other")),
+            Classification::default()
+        );
+    }
+
+    #[test]
+    fn tries_higher_priorities_first() {
+        let rules = rules(ACME, Default::default()).unwrap();
+        assert_eq!(
+            rules.classify(&record("RF12", "SAMPLE TEE", "1", "ACME")),
+            rule_fired("Samples are never tagged", false)
+        );
+    }
+
+    #[test]
+    fn rules_decide_before_upc_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tagged.txt");
+        std::fs::write(&path, "000000000042\n").unwrap();
+        let list = UpcList {
+            path,
+            from: None,
+            until: None,
+        };
+        let date = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+        let upcs = PreTaggedUpcs::load(&[list], date).unwrap();
+        let rules = rules(ACME, upcs).unwrap();
+
+        assert_eq!(
+            rules.classify(&record("A1", "TEE", "42", "")),
+            Classification {
+                pre_tagged: true,
+                reason: Some(Reason::UpcList("tagged.txt".to_owned())),
+            }
+        );
+        assert_eq!(
+            rules.classify(&record("A1", "SAMPLE TEE", "42", "")),
+            rule_fired("Samples are never tagged", false)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let error = |toml: &str| format!("{:#}", rules(toml, Default::default()).unwrap_err());
+
+        assert!(error("[[rfid]]\nname = \"a\"\n").contains("The RFID rule 'a' has no conditions"));
+        assert!(
+            error("[[rfid]]\nname = \"a\"\nall = [{ column = \"Season\", equals = \"1\" }]\n")
+                .contains("The PO file has no column `Season`")
+        );
+        assert!(error(
+            "[[rfid]]\nname = \"a\"\nall = [{ column = \"upc\", equals = \"1\", prefix = \"0\" }]\n"
+        )
+        .contains("must have exactly one of"));
+    }
+}
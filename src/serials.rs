@@ -0,0 +1,389 @@
+use crate::epc::MAX_SERIAL;
+use anyhow::{bail, Context, Result};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// The serial numbers given to the labels of one GTIN for one store of a PO.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reservation {
+    /// The GTIN the serial numbers are for, or the [crate::sscc::Sscc::counter_key] of the
+    /// SSCCs of cartons.
+    pub gtin: String,
+    pub first: u64,
+    pub last: u64,
+    pub po: String,
+    pub store: String,
+    /// The run that reserved the range, the local time it started and the id of its process,
+    /// e.g. `20220701-143000.123-4242`.
+    pub run: String,
+    /// Released ranges were never printed. Their serial numbers are skipped, unless they are the
+    /// last of their GTIN, in which case they are handed out again.
+    #[serde(default)]
+    pub released: bool,
+    /// Written ranges are in the files of a split that finished, so their labels may have been
+    /// printed and they are never released.
+    #[serde(default)]
+    pub written: bool,
+}
+
+/// Serial numbers wanted for `count` labels of `gtin` for the store of `po`.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub gtin: String,
+    pub count: u64,
+    pub po: String,
+    pub store: String,
+}
+
+/// The contents of the serials file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Ledger {
+    /// The next serial number of each GTIN.
+    next: BTreeMap<String, u64>,
+    reservations: Vec<Reservation>,
+}
+
+impl Ledger {
+    /// Sets the next serial number of `gtin` past its last range still in use, and forgets the
+    /// released ranges beyond it, which are handed out again.
+    fn rewind(&mut self, gtin: &str) {
+        let next = self
+            .reservations
+            .iter()
+            .filter(|reservation| reservation.gtin == gtin && !reservation.released)
+            .map(|reservation| reservation.last + 1)
+            .max()
+            .unwrap_or(1);
+
+        self.reservations
+            .retain(|reservation| reservation.gtin != gtin || reservation.first < next);
+        self.next.insert(gtin.to_owned(), next);
+    }
+}
+
+/// Hands out the serial numbers of EPCs so none is ever used twice, across runs and across the
+/// machines sharing the serials file.
+///
+/// The file is a JSON ledger of the next serial number of each GTIN and of every range reserved.
+/// It is only read and written while holding a lock on `<file>.lock`, so runs on other machines
+/// wait for each other.
+#[derive(Debug, Clone)]
+pub struct SerialAllocator {
+    path: PathBuf,
+    /// The largest serial number handed out.
+    max: u64,
+}
+
+impl SerialAllocator {
+    /// Hands out the serial numbers of SGTIN-96 EPCs from the serials file at `path`.
+    pub fn new(path: &Path) -> SerialAllocator {
+        SerialAllocator {
+            path: path.to_owned(),
+            max: MAX_SERIAL,
+        }
+    }
+
+    /// Hands out serial numbers up to `max` instead, e.g. the serial references of SSCCs.
+    pub fn with_max(self, max: u64) -> SerialAllocator {
+        SerialAllocator { max, ..self }
+    }
+
+    /// Returns the path of the serials file with `suffix` added, e.g. `serials.json.lock`.
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(suffix);
+        path.into()
+    }
+
+    /// Runs `update` on the ledger while holding the lock, then saves the ledger.
+    fn update<T>(&self, update: impl FnOnce(&mut Ledger) -> Result<T>) -> Result<T> {
+        let lock_path = self.with_suffix(".lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+        lock.lock_exclusive()
+            .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+
+        let mut ledger = self.read()?;
+        let result = update(&mut ledger)?;
+
+        // The ledger is written to a temporary file first, so it is never left half-written.
+        let part_path = self.with_suffix(".part");
+        let file = File::create(&part_path)
+            .with_context(|| format!("Failed to create {}", part_path.display()))?;
+        serde_json::to_writer_pretty(&file, &ledger)?;
+        file.sync_all()?;
+        std::fs::rename(&part_path, &self.path)
+            .with_context(|| format!("Failed to save {}", self.path.display()))?;
+
+        lock.unlock()?;
+        Ok(result)
+    }
+
+    fn read(&self) -> Result<Ledger> {
+        if !self.path.exists() {
+            return Ok(Ledger::default());
+        }
+        let text = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read the serials file {}", self.path.display()))?;
+
+        serde_json::from_str(&text)
+            .with_context(|| format!("Invalid serials file {}", self.path.display()))
+    }
+
+    /// Reserves a range of serial numbers for each of `requests`, all at once. The ranges are
+    /// recorded as reserved by `run`.
+    pub fn reserve(&self, requests: &[Request], run: &str) -> Result<Vec<Reservation>> {
+        self.update(|ledger| {
+            let mut reserved = vec![];
+            for request in requests.iter().filter(|request| request.count > 0) {
+                let next = ledger.next.entry(request.gtin.clone()).or_insert(1);
+                let last = *next + request.count - 1;
+                if last > self.max {
+                    bail!(
+                        "The serial numbers of {} have run out, {} are left",
+                        request.gtin,
+                        (self.max + 1).saturating_sub(*next)
+                    );
+                }
+
+                reserved.push(Reservation {
+                    gtin: request.gtin.clone(),
+                    first: *next,
+                    last,
+                    po: request.po.clone(),
+                    store: request.store.clone(),
+                    run: run.to_owned(),
+                    released: false,
+                    written: false,
+                });
+                *next = last + 1;
+            }
+
+            ledger.reservations.extend(reserved.iter().cloned());
+            Ok(reserved)
+        })
+    }
+
+    /// Returns every range reserved, oldest first.
+    pub fn reservations(&self) -> Result<Vec<Reservation>> {
+        // Without a lock file nothing has been reserved yet.
+        let lock_path = self.with_suffix(".lock");
+        let lock = File::open(&lock_path).ok();
+        if let Some(lock) = &lock {
+            lock.lock_shared()
+                .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+        }
+
+        Ok(self.read()?.reservations)
+    }
+
+    /// Marks the ranges of `run` as written, once every file of the split was saved.
+    pub fn finish(&self, run: &str) -> Result<()> {
+        self.update(|ledger| {
+            for reservation in &mut ledger.reservations {
+                if reservation.run == run && !reservation.released {
+                    reservation.written = true;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Releases the ranges of `run`, a run that printed none of their labels. Fails without
+    /// releasing any if the run wrote its files.
+    ///
+    /// Returns the ranges released.
+    pub fn release(&self, run: &str) -> Result<Vec<Reservation>> {
+        self.update(|ledger| {
+            let written = ledger
+                .reservations
+                .iter()
+                .filter(|reservation| reservation.run == run && reservation.written)
+                .count();
+            if written > 0 {
+                bail!(
+                    "Run {} wrote its files, so {} of its range(s) may have been printed and can't be released",
+                    run,
+                    written
+                );
+            }
+
+            let mut released = vec![];
+            for reservation in &mut ledger.reservations {
+                if !reservation.released && reservation.run == run {
+                    reservation.released = true;
+                    released.push(reservation.clone());
+                }
+            }
+
+            let mut gtins: Vec<String> = released.iter().map(|r| r.gtin.clone()).collect();
+            gtins.sort();
+            gtins.dedup();
+            for gtin in gtins {
+                ledger.rewind(&gtin);
+            }
+            Ok(released)
+        })
+    }
+}
+
+/// Picks out reservations by run, PO and GTIN. A field left `None` matches any reservation.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub run: Option<String>,
+    pub po: Option<String>,
+    pub gtin: Option<String>,
+}
+
+impl Selection {
+    pub fn matches(&self, reservation: &Reservation) -> bool {
+        self.run.as_ref().is_none_or(|run| *run == reservation.run)
+            && self.po.as_ref().is_none_or(|po| *po == reservation.po)
+            && self
+                .gtin
+                .as_ref()
+                .is_none_or(|gtin| *gtin == reservation.gtin)
+    }
+}
+
+/// Returns `reservations` as a table for people to read.
+pub fn table(reservations: &[Reservation]) -> String {
+    let mut table = format!(
+        "{:<14} {:>12} {:>12} {:<20} {:>5} {:<24} {}\n",
+        "GTIN", "FIRST", "LAST", "PO", "STORE", "RUN", "STATUS"
+    );
+    for reservation in reservations {
+        table.push_str(&format!(
+            "{:<14} {:>12} {:>12} {:<20} {:>5} {:<24} {}\n",
+            reservation.gtin,
+            reservation.first,
+            reservation.last,
+            reservation.po,
+            reservation.store,
+            reservation.run,
+            match (reservation.released, reservation.written) {
+                (true, _) => "released",
+                (false, true) => "written",
+                (false, false) => "reserved",
+            }
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GTIN: &str = "00614141234561";
+
+    fn request(count: u64, po: &str) -> Request {
+        Request {
+            gtin: GTIN.to_owned(),
+            count,
+            po: po.to_owned(),
+            store: po[po.len() - 3..].to_owned(),
+        }
+    }
+
+    fn release_run(serials: &SerialAllocator, run: &str) -> Vec<Reservation> {
+        serials.release(run).unwrap()
+    }
+
+    fn ranges(reservations: &[Reservation]) -> Vec<(u64, u64)> {
+        reservations.iter().map(|r| (r.first, r.last)).collect()
+    }
+
+    #[test]
+    fn reserves_consecutive_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let serials = SerialAllocator::new(&dir.path().join("serials.json"));
+
+        let first = serials.reserve(&[request(3, "po1-001")], "a").unwrap();
+        let second = serials
+            .reserve(&[request(2, "po1-001"), request(4, "po1-002")], "b")
+            .unwrap();
+
+        assert_eq!(ranges(&first), [(1, 3)]);
+        assert_eq!(ranges(&second), [(4, 5), (6, 9)]);
+    }
+
+    #[test]
+    fn reuses_released_last_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let serials = SerialAllocator::new(&dir.path().join("serials.json"));
+        serials.reserve(&[request(3, "po1-001")], "a").unwrap();
+        serials.reserve(&[request(5, "po1-002")], "b").unwrap();
+
+        assert_eq!(ranges(&release_run(&serials, "b")), [(4, 8)]);
+        let again = serials.reserve(&[request(2, "po1-003")], "c").unwrap();
+
+        assert_eq!(ranges(&again), [(4, 5)]);
+        let kept = serials.reservations().unwrap();
+        assert_eq!(ranges(&kept), [(1, 3), (4, 5)]);
+    }
+
+    #[test]
+    fn never_reuses_released_middle_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let serials = SerialAllocator::new(&dir.path().join("serials.json"));
+        serials.reserve(&[request(3, "po1-001")], "a").unwrap();
+        serials.reserve(&[request(5, "po1-002")], "b").unwrap();
+        serials.reserve(&[request(2, "po1-003")], "c").unwrap();
+
+        release_run(&serials, "b");
+        let next = serials.reserve(&[request(4, "po1-004")], "d").unwrap();
+
+        assert_eq!(ranges(&next), [(11, 14)]);
+        // Once every range after it is released too, none of them was printed, so they are
+        // handed out again.
+        release_run(&serials, "d");
+        release_run(&serials, "c");
+        let last = serials.reserve(&[request(1, "po1-005")], "e").unwrap();
+        assert_eq!(ranges(&last), [(4, 4)]);
+    }
+
+    #[test]
+    fn never_releases_written_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let serials = SerialAllocator::new(&dir.path().join("serials.json"));
+        serials
+            .reserve(&[request(3, "po1-001"), request(2, "po1-002")], "a")
+            .unwrap();
+        serials.finish("a").unwrap();
+
+        let error = serials.release("a").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("2 of its range(s) may have been printed"));
+        let kept = serials.reservations().unwrap();
+        assert!(kept.iter().all(|r| r.written && !r.released));
+        assert_eq!(
+            ranges(&serials.reserve(&[request(1, "po1-003")], "b").unwrap()),
+            [(6, 6)]
+        );
+    }
+
+    #[test]
+    fn fails_when_serials_run_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let serials = SerialAllocator::new(&dir.path().join("serials.json"));
+        let all_but_one = request(MAX_SERIAL - 1, "po1-001");
+        serials.reserve(&[all_but_one], "a").unwrap();
+
+        let error = serials.reserve(&[request(2, "po1-002")], "b").unwrap_err();
+        assert!(error.to_string().contains("1 are left"));
+        // A failed reservation reserves nothing.
+        assert_eq!(
+            ranges(&serials.reservations().unwrap()),
+            [(1, MAX_SERIAL - 1)]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::epc::check_digit;
+use crate::packing::PackingList;
+use crate::zpl;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Settings of the SSCCs of the cartons shipped to each store, e.g.
+///
+/// ```toml
+/// [sscc]
+/// extension = 0
+/// company_prefix = "0614141"
+/// ```
+///
+/// Serial references are handed out from the `counter` file, which can be shared by every
+/// machine printing ship labels, see [crate::serials::SerialAllocator].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SsccConfig {
+    /// The extension digit leading every SSCC, from 0 to 9.
+    pub extension: u8,
+    /// The GS1 company prefix of the shipper.
+    pub company_prefix: String,
+    /// The file keeping track of the serial references used, `lisa_sscc.json` when left out.
+    pub counter: PathBuf,
+}
+
+impl Default for SsccConfig {
+    fn default() -> Self {
+        SsccConfig {
+            extension: 0,
+            company_prefix: String::new(),
+            counter: PathBuf::from("lisa_sscc.json"),
+        }
+    }
+}
+
+/// Numbers cartons with SSCC-18s.
+#[derive(Debug, Clone)]
+pub struct Sscc {
+    extension: u8,
+    company_prefix: String,
+}
+
+impl Sscc {
+    /// Checks the extension digit and company prefix of `config`.
+    pub fn new(config: &SsccConfig) -> Result<Sscc> {
+        if config.extension > 9 {
+            bail!(
+                "The SSCC extension digit must be from 0 to 9, not {}",
+                config.extension
+            );
+        }
+
+        let prefix = config.company_prefix.trim();
+        if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_digit()) {
+            bail!("The SSCC company prefix `{}` must be digits", prefix);
+        }
+        if !(6..=12).contains(&prefix.len()) {
+            bail!(
+                "The SSCC company prefix `{}` must be from 6 to 12 digits long, not {}",
+                prefix,
+                prefix.len()
+            );
+        }
+
+        Ok(Sscc {
+            extension: config.extension,
+            company_prefix: prefix.to_owned(),
+        })
+    }
+
+    /// Returns the name the serial references of these SSCCs are counted under, the extension
+    /// digit followed by the company prefix.
+    pub fn counter_key(&self) -> String {
+        format!("{}{}", self.extension, self.company_prefix)
+    }
+
+    /// Returns the number of digits of the serial reference, what the company prefix leaves of
+    /// the 16 digits between the extension digit and the check digit.
+    fn serial_digits(&self) -> usize {
+        16 - self.company_prefix.len()
+    }
+
+    /// Returns the largest serial reference an SSCC with this company prefix can hold.
+    pub fn max_serial(&self) -> u64 {
+        10u64.pow(self.serial_digits() as u32) - 1
+    }
+
+    /// Returns the 18 digits of the SSCC with the serial reference `serial`.
+    pub fn encode(&self, serial: u64) -> Result<String> {
+        if serial > self.max_serial() {
+            bail!(
+                "The serial reference {} is too large for an SSCC with the company prefix {}, the largest is {}",
+                serial,
+                self.company_prefix,
+                self.max_serial()
+            );
+        }
+
+        let digits = format!(
+            "{}{}{:0width$}",
+            self.extension,
+            self.company_prefix,
+            serial,
+            width = self.serial_digits()
+        );
+        Ok(format!("{}{}", digits, check_digit(&digits)))
+    }
+
+    /// Returns `sscc` as printed under its barcode, e.g. `(00) 0 0614141 123456789 0`.
+    pub fn human_readable(&self, sscc: &str) -> String {
+        let prefix_end = 1 + self.company_prefix.len();
+        format!(
+            "(00) {} {} {} {}",
+            &sscc[..1],
+            &sscc[1..prefix_end],
+            &sscc[prefix_end..17],
+            &sscc[17..]
+        )
+    }
+
+    /// Returns the ZPL of the ship label of `carton`, a 4x6 inch label at 203 dpi with a GS1-128
+    /// barcode of its SSCC.
+    pub fn ship_label(&self, carton: &ShipLabel) -> Result<String> {
+        if carton.sscc.len() != 18 {
+            bail!("`{}` is not an SSCC-18", carton.sscc);
+        }
+
+        // Up to three lines of the ship-to address under the store number.
+        let ship_to: String = carton
+            .ship_to
+            .iter()
+            .take(3)
+            .enumerate()
+            .map(|(i, line)| {
+                format!(
+                    "^FO40,{}^A0N,30,30^FD{}^FS\n",
+                    90 + 35 * i,
+                    zpl::field(line)
+                )
+            })
+            .collect();
+
+        Ok(format!(
+            "^XA
+^CI28
+^FO40,40^A0N,40,40^FDSHIP TO STORE {store}^FS
+{ship_to}^FO40,210^A0N,30,30^FDPO {po}^FS
+^FO40,250^A0N,60,60^FDCARTON {number} OF {cartons}^FS
+^FO40,320^A0N,30,30^FD{class}^FS
+^FO30,370^GB750,4,4^FS
+^FO40,400^A0N,30,30^FDSSCC^FS
+^FO60,450^BY3^BCN,250,N,N,N,D^FD(00){sscc}^FS
+^FO60,720^A0N,40,40^FD{human}^FS
+^XZ
+",
+            store = zpl::field(carton.store),
+            ship_to = ship_to,
+            po = zpl::field(carton.po),
+            number = carton.number,
+            cartons = carton.cartons,
+            class = zpl::field(carton.class),
+            sscc = carton.sscc,
+            human = self.human_readable(carton.sscc),
+        ))
+    }
+
+    /// Writes the ship label of each carton of `plan`, the packing list of a store, addressed
+    /// to `ship_to`.
+    pub fn write_ship_labels(
+        &self,
+        plan: &PackingList,
+        ship_to: &[String],
+        mut out: impl Write,
+    ) -> Result<()> {
+        for carton in &plan.cartons {
+            let label = ShipLabel {
+                ship_to,
+                sscc: carton.sscc.as_deref().unwrap_or_default(),
+                po: plan.po.base(),
+                store: plan.po.store(),
+                number: carton.number,
+                cartons: plan.cartons.len(),
+                class: &carton.class.name,
+            };
+            out.write_all(self.ship_label(&label)?.as_bytes())?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// The values printed on the ship label of a carton.
+#[derive(Debug, Clone, Copy)]
+pub struct ShipLabel<'a> {
+    pub sscc: &'a str,
+    pub po: &'a str,
+    pub store: &'a str,
+    /// The ship-to address of the store, from the store directory, or empty.
+    pub ship_to: &'a [String],
+    /// The number of the carton within its store, from 1.
+    pub number: usize,
+    /// The number of cartons shipped to the store.
+    pub cartons: usize,
+    /// The name of the kind of carton.
+    pub class: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sscc(extension: u8, company_prefix: &str) -> Result<Sscc> {
+        Sscc::new(&SsccConfig {
+            extension,
+            company_prefix: company_prefix.to_owned(),
+            ..SsccConfig::default()
+        })
+    }
+
+    #[test]
+    fn encodes_the_gs1_example() {
+        let sscc = sscc(1, "0614141").unwrap();
+        let encoded = sscc.encode(234567890).unwrap();
+        assert_eq!(encoded, "106141412345678908");
+        assert_eq!(sscc.human_readable(&encoded), "(00) 1 0614141 234567890 8");
+        assert_eq!(sscc.counter_key(), "10614141");
+    }
+
+    #[test]
+    fn bounds_the_serial_by_the_prefix_length() {
+        for length in 6..=12 {
+            let sscc = sscc(0, &"9".repeat(length)).unwrap();
+            let max = 10u64.pow(16 - length as u32) - 1;
+            assert_eq!(sscc.max_serial(), max);
+            assert_eq!(sscc.encode(max).unwrap().len(), 18);
+            assert_eq!(sscc.encode(0).unwrap().len(), 18);
+            assert!(sscc.encode(max + 1).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_bad_prefixes_and_extensions() {
+        assert!(sscc(0, "06141").is_err());
+        assert!(sscc(0, "0614141234567").is_err());
+        assert!(sscc(0, "").is_err());
+        assert!(sscc(0, "061414A").is_err());
+        assert!(sscc(10, "0614141").is_err());
+        assert!(sscc(9, " 0614141 ").is_ok());
+    }
+
+    #[test]
+    fn writes_the_sscc_on_the_ship_label() {
+        let sscc = sscc(1, "0614141").unwrap();
+        let ship_to = ["Downtown".to_owned(), "Springfield, IL 62701".to_owned()];
+        let mut label = ShipLabel {
+            sscc: "106141412345678908",
+            po: "po14423",
+            store: "001",
+            ship_to: &ship_to,
+            number: 2,
+            cartons: 3,
+            class: "Standard",
+        };
+        let zpl = sscc.ship_label(&label).unwrap();
+        assert!(zpl.contains("^FD(00)106141412345678908^FS"));
+        assert!(zpl.contains("^FD(00) 1 0614141 234567890 8^FS"));
+        assert!(zpl.contains("^FDCARTON 2 OF 3^FS"));
+        assert!(zpl.contains("^FO40,125^A0N,30,30^FDSpringfield, IL 62701^FS"));
+
+        label.sscc = "10614141234567890";
+        assert!(sscc.ship_label(&label).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::location::Location;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+use toml::Spanned;
+
+/// A store from the list of stores to be processed.
+#[derive(Debug, Clone)]
+pub struct Store {
+    /// The three digit store number, e.g. `001`.
+    pub number: String,
+    pub name: Option<String>,
+    pub region: Option<String>,
+    /// Stores that are disabled stay in the list but are not processed.
+    pub enabled: bool,
+}
+
+/// The list of stores the end user wants split out of a PO.
+///
+/// The list is either a TOML file, with one `[[store]]` table per store or range of stores:
+///
+/// ```toml
+/// [[store]]
+/// number = "001"
+/// name = "Downtown"
+/// region = "North"
+///
+/// [[store]]
+/// number = "100-120"
+/// region = "East"
+/// enabled = false
+/// ```
+///
+/// or the original text format, store numbers separated by commas and/or new lines.
+#[derive(Debug, Clone, Default)]
+pub struct StoreList {
+    stores: Vec<Store>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoreFile {
+    #[serde(default, rename = "store")]
+    stores: Vec<StoreEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoreEntry {
+    number: Spanned<String>,
+    name: Option<String>,
+    region: Option<String>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl fmt::Display for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.number)?;
+        if let Some(name) = &self.name {
+            write!(f, " {}", name)?;
+        }
+        if let Some(region) = &self.region {
+            write!(f, " ({})", region)?;
+        }
+        Ok(())
+    }
+}
+
+impl StoreList {
+    /// Reads the store list at `path`. Files ending in `.toml` are read as TOML, anything else
+    /// as the original comma separated text format.
+    pub fn load(path: &Path) -> Result<StoreList> {
+        let text = std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "Could not read the file containing the stores to search for, {}",
+                path.display()
+            )
+        })?;
+
+        let is_toml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        let list = match is_toml {
+            true => StoreList::parse_toml(&text),
+            false => StoreList::parse_text(&text),
+        };
+
+        list.with_context(|| format!("Invalid store list {}", path.display()))
+    }
+
+    /// Parses a TOML store list.
+    pub fn parse_toml(text: &str) -> Result<StoreList> {
+        let file: StoreFile = toml::from_str(text)?;
+        let mut stores = vec![];
+
+        for entry in file.stores {
+            let (line, column) = line_col(text, entry.number.start());
+            // Skip the opening quote so the column points at the number itself.
+            let numbers = expand(entry.number.get_ref(), line, column + 1)?;
+
+            stores.extend(numbers.into_iter().map(|number| Store {
+                number,
+                name: entry.name.clone(),
+                region: entry.region.clone(),
+                enabled: entry.enabled,
+            }));
+        }
+
+        Ok(StoreList { stores })
+    }
+
+    /// Parses the original store list format, e.g. `001,010,011`.
+    ///
+    /// Store numbers may be separated by commas, new lines or both, and ranges such as `100-120`
+    /// are accepted as in the TOML format.
+    pub fn parse_text(text: &str) -> Result<StoreList> {
+        let mut stores = vec![];
+
+        for (line_index, line) in text.lines().enumerate() {
+            let mut offset = 0;
+            for token in line.split(',') {
+                let trimmed = token.trim();
+                let column = offset + (token.len() - token.trim_start().len()) + 1;
+                offset += token.len() + 1;
+
+                if trimmed.is_empty() {
+                    continue;
+                }
+
+                stores.extend(
+                    expand(trimmed, line_index + 1, column)?
+                        .into_iter()
+                        .map(|number| Store {
+                            number,
+                            name: None,
+                            region: None,
+                            enabled: true,
+                        }),
+                );
+            }
+        }
+
+        Ok(StoreList { stores })
+    }
+
+    /// Returns every store in the list, including disabled ones.
+    pub fn stores(&self) -> &[Store] {
+        &self.stores
+    }
+
+    /// Returns the number of each enabled store, in the order of the list and without
+    /// duplicates.
+    pub fn numbers(&self) -> Vec<String> {
+        let mut numbers: Vec<String> = vec![];
+
+        for store in self.stores.iter().filter(|store| store.enabled) {
+            if !numbers.contains(&store.number) {
+                numbers.push(store.number.clone());
+            }
+        }
+
+        numbers
+    }
+}
+
+/// The stores of a PO file to be processed.
+#[derive(Debug, Clone)]
+pub enum StoreSelection {
+    /// Only the stores given by the store list.
+    List(Vec<String>),
+    /// Every store found in the PO file, except those in `exclude`.
+    All { exclude: Vec<String> },
+}
+
+impl StoreSelection {
+    /// Every store, except those given as store numbers separated by commas, e.g. `045,101`.
+    pub fn all_except(exclude: &str) -> Result<StoreSelection> {
+        let exclude = StoreList::parse_text(exclude).context("Invalid list of excluded stores")?;
+
+        Ok(StoreSelection::All {
+            exclude: exclude.numbers(),
+        })
+    }
+
+    /// Returns `true` if the store numbered `store` is to be processed.
+    pub fn includes(&self, store: &str) -> bool {
+        match self {
+            StoreSelection::List(list) => list.iter().any(|number| number == store),
+            StoreSelection::All { exclude } => !exclude.iter().any(|number| number == store),
+        }
+    }
+}
+
+/// Returns the 1-based line and column of the byte `offset` within `text`.
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+    (line, column)
+}
+
+/// Expands a store number, or a range of store numbers such as `100-120`, found at `line` and
+/// `column` of the store list.
+fn expand(value: &str, line: usize, column: usize) -> Result<Vec<String>> {
+    let location = Location::new(line as u64, Some(column));
+    let check = |number: &str| -> Result<u32> {
+        if number.len() != 3 || !number.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow!(
+                "`{}` is not a three digit store number, e.g. store 1 is written as `001`",
+                number
+            ))
+            .context(location);
+        }
+        Ok(number.parse()?)
+    };
+
+    match value.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (check(start.trim())?, check(end.trim())?);
+            if start > end {
+                return Err(anyhow!("the range `{}` ends before it starts", value))
+                    .context(location);
+            }
+            Ok((start..=end)
+                .map(|number| format!("{:03}", number))
+                .collect())
+        }
+        None => Ok(vec![format!("{:03}", check(value.trim())?)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml_stores_and_ranges() {
+        let list = StoreList::parse_toml(
+            r#"
+[[store]]
+number = "001"
+name = "Downtown"
+region = "North"
+
+[[store]]
+number = "100-103"
+region = "East"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(list.numbers(), ["001", "100", "101", "102", "103"]);
+        assert_eq!(list.stores()[0].to_string(), "001 Downtown (North)");
+        assert_eq!(list.stores()[4].region.as_deref(), Some("East"));
+    }
+
+    #[test]
+    fn keeps_disabled_stores_out_of_the_numbers() {
+        let list = StoreList::parse_toml(
+            r#"
+[[store]]
+number = "001"
+
+[[store]]
+number = "002-003"
+enabled = false
+
+[[store]]
+number = "001"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(list.stores().len(), 4);
+        assert_eq!(list.numbers(), ["001"]);
+    }
+
+    #[test]
+    fn parses_the_text_format() {
+        let list = StoreList::parse_text("001,010, 011\n\n045\n100-102,").unwrap();
+        assert_eq!(
+            list.numbers(),
+            ["001", "010", "011", "045", "100", "101", "102"]
+        );
+    }
+
+    #[test]
+    fn locates_bad_store_numbers() {
+        let error = StoreList::parse_text("001,010\n011, 12").unwrap_err();
+        assert_eq!(Location::of(&error), Some(Location::new(2, Some(6))));
+        assert!(format!("{:#}", error).contains("`12` is not a three digit store number"));
+
+        let error = StoreList::parse_toml("[[store]]\nnumber = \"120-100\"\n").unwrap_err();
+        assert_eq!(Location::of(&error), Some(Location::new(2, Some(11))));
+        assert!(format!("{:#}", error).contains("the range `120-100` ends before it starts"));
+    }
+
+    #[test]
+    fn excludes_stores_from_all() {
+        let selection = StoreSelection::all_except("045,101").unwrap();
+        assert!(selection.includes("001"));
+        assert!(!selection.includes("045"));
+        assert!(!StoreSelection::List(vec!["001".to_owned()]).includes("002"));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::columns::normalize;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use toml::value::Datetime;
+
+/// A list of UPCs that vendors ship with an RFID inlay already applied.
+///
+/// Lists are added to the config file, each with the dates it is in effect, for example:
+///
+/// ```toml
+/// [[upc_list]]
+/// path = "lists/acme_tagged.csv"
+/// from = 2022-07-01
+/// until = 2022-12-31
+/// ```
+///
+/// A list ending in `.csv` must have a `UPC` column. Any other file lists UPCs separated by
+/// commas and/or new lines.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpcList {
+    pub path: PathBuf,
+    /// The first day the list is in effect. Without it the list is in effect from any date.
+    pub from: Option<Datetime>,
+    /// The last day the list is in effect. Without it the list never expires.
+    pub until: Option<Datetime>,
+}
+
+impl UpcList {
+    /// Returns the name the list is reported by, its file name.
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+
+    /// Returns the first and last day the list is in effect, if set.
+    fn dates(&self) -> Result<(Option<NaiveDate>, Option<NaiveDate>)> {
+        let parse = |datetime: &Option<Datetime>, key: &str| -> Result<Option<NaiveDate>> {
+            datetime
+                .as_ref()
+                .map(|datetime| {
+                    NaiveDate::parse_from_str(&datetime.to_string(), "%Y-%m-%d").with_context(
+                        || {
+                            format!(
+                                "`{}` of the UPC list {} must be a date, e.g. 2022-07-01",
+                                key,
+                                self.path.display()
+                            )
+                        },
+                    )
+                })
+                .transpose()
+        };
+        let (from, until) = (parse(&self.from, "from")?, parse(&self.until, "until")?);
+
+        if let (Some(from), Some(until)) = (from, until) {
+            if until < from {
+                bail!(
+                    "The UPC list {} is never in effect, `until` is before `from`",
+                    self.path.display()
+                );
+            }
+        }
+        Ok((from, until))
+    }
+
+    /// Returns `true` if the list is in effect on `date`.
+    pub fn is_effective(&self, date: NaiveDate) -> Result<bool> {
+        let (from, until) = self.dates()?;
+
+        Ok(from.is_none_or(|from| from <= date) && until.is_none_or(|until| date <= until))
+    }
+
+    /// Reads the UPCs of the list.
+    fn load(&self) -> Result<Vec<String>> {
+        let text = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read the UPC list {}", self.path.display()))?;
+
+        let is_csv = self
+            .path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let upcs = match is_csv {
+            true => parse_csv(&text),
+            false => Ok(parse_text(&text)),
+        };
+
+        upcs.with_context(|| format!("Invalid UPC list {}", self.path.display()))
+    }
+}
+
+fn parse_csv(text: &str) -> Result<Vec<String>> {
+    let mut rdr = csv::Reader::from_reader(text.as_bytes());
+    let index = rdr
+        .headers()?
+        .iter()
+        .position(|header| ["upc", "upccode"].contains(&normalize(header).as_str()))
+        .ok_or_else(|| anyhow!("The list has no `UPC` column"))?;
+
+    let mut upcs = vec![];
+    for record in rdr.records() {
+        let record = record?;
+        upcs.push(record.get(index).unwrap_or_default().to_owned());
+    }
+    Ok(upcs)
+}
+
+fn parse_text(text: &str) -> Vec<String> {
+    text.split([',', '\n'])
+        .map(|upc| upc.trim().to_owned())
+        .collect()
+}
+
+/// Returns `upc` in the form UPCs are compared in. Leading zeros are ignored, so a UPC-A and
+/// the same code written as an EAN-13 or GTIN-14 match.
+fn key(upc: &str) -> Option<String> {
+    let upc = upc.trim();
+    if upc.is_empty() {
+        return None;
+    }
+    match upc.chars().all(|c| c.is_ascii_digit()) {
+        true => Some(upc.trim_start_matches('0').to_owned()),
+        false => Some(upc.to_owned()),
+    }
+}
+
+/// The UPCs of every list in effect on the day of the run.
+#[derive(Debug, Clone, Default)]
+pub struct PreTaggedUpcs {
+    /// The name of the list each UPC was found on.
+    upcs: HashMap<String, String>,
+}
+
+impl PreTaggedUpcs {
+    /// Reads the `lists` in effect on `date`. Lists that aren't are skipped without being read.
+    pub fn load(lists: &[UpcList], date: NaiveDate) -> Result<PreTaggedUpcs> {
+        let mut upcs = HashMap::new();
+
+        for list in lists {
+            if !list.is_effective(date)? {
+                info!(
+                    "Skipping the UPC list {}, not in effect on {}",
+                    list.name(),
+                    date
+                );
+                continue;
+            }
+
+            let name = list.name();
+            let found: Vec<String> = list.load()?.iter().filter_map(|upc| key(upc)).collect();
+            info!("Loaded {} UPC(s) from the UPC list {}", found.len(), name);
+            for upc in found {
+                upcs.entry(upc).or_insert_with(|| name.clone());
+            }
+        }
+
+        Ok(PreTaggedUpcs { upcs })
+    }
+
+    /// Returns the name of the list `upc` is on, if any.
+    pub fn find(&self, upc: &str) -> Option<&str> {
+        key(upc).and_then(|upc| self.upcs.get(&upc).map(|name| name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(path: &str, from: Option<&str>, until: Option<&str>) -> UpcList {
+        UpcList {
+            path: PathBuf::from(path),
+            from: from.map(|date| date.parse().unwrap()),
+            until: until.map(|date| date.parse().unwrap()),
+        }
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn is_in_effect_between_its_dates() {
+        let list = list("tagged.csv", Some("2022-07-01"), Some("2022-12-31"));
+        assert!(!list.is_effective(date("2022-06-30")).unwrap());
+        assert!(list.is_effective(date("2022-07-01")).unwrap());
+        assert!(list.is_effective(date("2022-12-31")).unwrap());
+        assert!(!list.is_effective(date("2023-01-01")).unwrap());
+    }
+
+    #[test]
+    fn is_in_effect_without_dates() {
+        assert!(list("a.csv", None, None)
+            .is_effective(date("1999-01-01"))
+            .unwrap());
+        let from = list("a.csv", Some("2022-07-01"), None);
+        assert!(from.is_effective(date("2099-01-01")).unwrap());
+        assert!(!from.is_effective(date("2022-06-30")).unwrap());
+    }
+
+    #[test]
+    fn rejects_bad_dates() {
+        let never = list("a.csv", Some("2022-07-01"), Some("2022-06-30"));
+        assert!(never.is_effective(date("2022-07-01")).is_err());
+        let time = list("a.csv", Some("2022-07-01T10:00:00"), None);
+        assert!(time.is_effective(date("2022-07-01")).is_err());
+    }
+
+    #[test]
+    fn loads_only_the_lists_in_effect() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("acme.csv");
+        std::fs::write(&csv, "Style,UPC Code\nA1,000614141000012\nA2,\n").unwrap();
+        let lists = [
+            UpcList {
+                path: csv,
+                from: None,
+                until: None,
+            },
+            // Never read, so the missing file is no error.
+            list("missing.txt", None, Some("2020-01-01")),
+        ];
+
+        let upcs = PreTaggedUpcs::load(&lists, date("2022-07-01")).unwrap();
+        assert_eq!(upcs.find("614141000012"), Some("acme.csv"));
+        assert_eq!(upcs.find("0614141000012"), Some("acme.csv"));
+        assert_eq!(upcs.find("614141000029"), None);
+        assert_eq!(upcs.find(""), None);
+    }
+
+    #[test]
+    fn parses_text_lists() {
+        assert_eq!(parse_text("1, 2\n3\n"), ["1", "2", "3", ""]);
+        assert!(parse_csv("Style\nA1\n").is_err());
+    }
+}
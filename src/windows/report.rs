@@ -1,9 +1,281 @@
+use crate::report::{self, Report, ReportFormat, StoreTotals};
 use eframe::egui;
-use egui::Context;
+use std::cmp::Ordering;
+use std::rc::Rc;
 
-pub fn report_win() {
-    let ctx = &egui::Context::default();
-    egui::Window::new("My Window").show(ctx, |ui| {
-        ui.label("Hello World!");
-    });
+/// The columns the per-store table of the report can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SortColumn {
+    Po,
+    #[default]
+    Store,
+    Name,
+    Total,
+    LabelsToPrint,
+    PreTagged,
+    Boxes,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 7] = [
+        SortColumn::Po,
+        SortColumn::Store,
+        SortColumn::Name,
+        SortColumn::Total,
+        SortColumn::LabelsToPrint,
+        SortColumn::PreTagged,
+        SortColumn::Boxes,
+    ];
+
+    fn heading(&self) -> &str {
+        match self {
+            SortColumn::Po => "PO",
+            SortColumn::Store => "Store",
+            SortColumn::Name => "Name",
+            SortColumn::Total => "Total",
+            SortColumn::LabelsToPrint => "Labels to print",
+            SortColumn::PreTagged => "Pre-tagged",
+            SortColumn::Boxes => "Boxes",
+        }
+    }
+
+    fn compare(&self, a: &StoreTotals, b: &StoreTotals) -> Ordering {
+        match self {
+            SortColumn::Po => a.po.cmp(&b.po),
+            SortColumn::Store => a.store.cmp(&b.store),
+            SortColumn::Name => a.location().cmp(&b.location()),
+            SortColumn::Total => a.total.cmp(&b.total),
+            SortColumn::LabelsToPrint => a.labels_to_print.cmp(&b.labels_to_print),
+            SortColumn::PreTagged => a.pre_tagged.cmp(&b.pre_tagged),
+            SortColumn::Boxes => a.boxes.cmp(&b.boxes),
+        }
+    }
+}
+
+/// The window showing the last report produced in GUI mode.
+///
+/// The window floats above the main window, or can be docked to its right side.
+#[derive(Debug, Default)]
+pub struct ReportWindow {
+    /// The report shown, shared with each frame drawn so it isn't copied every frame.
+    report: Option<Rc<Report>>,
+    open: bool,
+    docked: bool,
+    sort: SortColumn,
+    descending: bool,
+    /// The outcome of the last export or copy.
+    status: Option<String>,
+}
+
+impl ReportWindow {
+    /// Shows `report` in the window, opening it if it was closed.
+    pub fn set_report(&mut self, report: Report) {
+        self.report = Some(Rc::new(report));
+        self.open = true;
+        self.status = None;
+    }
+
+    /// Returns `true` if there is a report that can be shown.
+    pub fn has_report(&self) -> bool {
+        self.report.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Draws the window. Must be called before the central panel of the app is shown, so the
+    /// docked window can take its space.
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open || self.report.is_none() {
+            return;
+        }
+
+        match self.docked {
+            true => {
+                egui::SidePanel::right("report_panel")
+                    .resizable(true)
+                    .default_width(420.0)
+                    .show(ctx, |ui| self.contents(ui));
+            }
+            false => {
+                let mut open = self.open;
+                egui::Window::new("Report")
+                    .open(&mut open)
+                    .default_width(420.0)
+                    .resizable(true)
+                    .show(ctx, |ui| self.contents(ui));
+                self.open = open;
+            }
+        }
+    }
+
+    fn contents(&mut self, ui: &mut egui::Ui) {
+        let report = match &self.report {
+            Some(report) => Rc::clone(report),
+            None => return,
+        };
+
+        ui.horizontal(|ui| {
+            let dock = match self.docked {
+                true => "Undock",
+                false => "Dock",
+            };
+            if ui.button(dock).clicked() {
+                self.docked = !self.docked;
+            }
+            if self.docked && ui.button("Close").clicked() {
+                self.open = false;
+            }
+        });
+        ui.separator();
+
+        ui.heading("Totals for this order");
+        egui::Grid::new("report_totals")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for (label, value) in [
+                    ("Total stores", report.num_stores),
+                    ("Total items", report.total),
+                    ("Needs RFID printed", report.labels_to_print),
+                    ("May not need RFID", report.pre_tagged),
+                    ("Total boxes", report.boxes),
+                ] {
+                    ui.label(label);
+                    ui.label(value.to_string());
+                    ui.end_row();
+                }
+                for carton in &report.cartons {
+                    ui.label(format!(
+                        "    {} ({} per box)",
+                        carton.carton, carton.capacity
+                    ))
+                    .on_hover_text(format!("{} item(s)", carton.qty));
+                    ui.label(carton.boxes.to_string());
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui.button("Export CSV").clicked() {
+                self.export(&report, ReportFormat::Csv);
+            }
+            if ui.button("Export JSON").clicked() {
+                self.export(&report, ReportFormat::Json);
+            }
+            if ui.button("Export XLSX").clicked() {
+                self.export(&report, ReportFormat::Xlsx);
+            }
+            if ui.button("Copy").clicked() {
+                ui.output().copied_text = report.to_string();
+                self.status = Some("Copied the report to the clipboard.".to_owned());
+            }
+        });
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+        ui.separator();
+
+        if !report.unknown_stores.is_empty() {
+            ui.colored_label(
+                egui::Color32::RED,
+                report::unknown_summary(&report.unknown_stores),
+            );
+            ui.separator();
+        }
+
+        if !report.pre_tagged_lines.is_empty() {
+            let heading = format!(
+                "Pre-tagged lines: {}",
+                report::zeroed_summary(&report.pre_tagged_lines)
+            );
+            egui::CollapsingHeader::new(heading).show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_source("report_pre_tagged_scroll")
+                    .max_height(160.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("report_pre_tagged")
+                            .striped(true)
+                            .num_columns(5)
+                            .show(ui, |ui| {
+                                for heading in ["Line", "PO", "UPC", "Qty", "Reason"] {
+                                    ui.strong(heading);
+                                }
+                                ui.end_row();
+
+                                for line in &report.pre_tagged_lines {
+                                    ui.label(line.line.to_string());
+                                    ui.label(&line.po);
+                                    ui.label(&line.upc);
+                                    ui.label(line.qty.to_string());
+                                    ui.label(line.reason.to_string());
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+            ui.separator();
+        }
+
+        let mut stores: Vec<&StoreTotals> = report.stores.iter().collect();
+        stores.sort_by(|a, b| match self.descending {
+            true => self.sort.compare(b, a),
+            false => self.sort.compare(a, b),
+        });
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("report_stores")
+                .striped(true)
+                .num_columns(SortColumn::ALL.len())
+                .show(ui, |ui| {
+                    for column in SortColumn::ALL {
+                        let heading = match (self.sort == column, self.descending) {
+                            (true, false) => format!("{} ⏶", column.heading()),
+                            (true, true) => format!("{} ⏷", column.heading()),
+                            (false, _) => column.heading().to_owned(),
+                        };
+                        if ui.selectable_label(self.sort == column, heading).clicked() {
+                            self.descending = self.sort == column && !self.descending;
+                            self.sort = column;
+                        }
+                    }
+                    ui.end_row();
+
+                    for store in stores {
+                        ui.label(&store.po);
+                        ui.label(&store.store);
+                        ui.label(store.location());
+                        ui.label(store.total.to_string());
+                        ui.label(store.labels_to_print.to_string());
+                        ui.label(store.pre_tagged.to_string());
+                        ui.label(store.boxes.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    /// Asks where to save the report and writes it in `format`.
+    fn export(&mut self, report: &Report, format: ReportFormat) {
+        let extension = format.extension();
+        let path = rfd::FileDialog::new()
+            .add_filter(extension, &[extension])
+            .set_file_name(&format!("report.{}", extension))
+            .set_title("Save report as...")
+            .save_file();
+
+        let path = match path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result = report
+            .render(format)
+            .and_then(|contents| Ok(std::fs::write(&path, contents)?));
+        self.status = Some(match result {
+            Ok(()) => format!("Saved the report to {}", path.display()),
+            Err(e) => format!("Could not save the report: {:#}", e),
+        });
+    }
 }
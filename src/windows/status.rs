@@ -0,0 +1,66 @@
+use crate::location::Location;
+use eframe::egui;
+
+/// The outcome of the last job run from the GUI, shown in a bar at the bottom of the window.
+///
+/// Errors are shown here instead of stopping LISA, so the input can be fixed and the job run
+/// again.
+#[derive(Debug, Default)]
+pub enum Status {
+    #[default]
+    Idle,
+    Done(String),
+    Failed {
+        message: String,
+        /// The line and column of the input file the error was found on, when known.
+        location: Option<Location>,
+    },
+}
+
+impl Status {
+    /// The status of a job that failed with `error`. The location of the error, if any, is
+    /// taken out of the message to be shown on its own.
+    pub fn failed(error: &anyhow::Error) -> Status {
+        error!("{:?}", error);
+        let location = Location::of(error);
+        let message = error
+            .chain()
+            .map(|cause| cause.to_string())
+            .filter(|cause| Some(cause) != location.map(|l| l.to_string()).as_ref())
+            .collect::<Vec<String>>()
+            .join(": ");
+
+        Status::Failed { message, location }
+    }
+
+    /// Draws the status bar. Must be called before the central panel of the app is shown.
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if let Status::Idle = self {
+            return;
+        }
+
+        let mut dismiss = false;
+        egui::TopBottomPanel::bottom("status_panel").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                match self {
+                    Status::Idle => {}
+                    Status::Done(message) => {
+                        ui.colored_label(egui::Color32::GREEN, message.as_str());
+                    }
+                    Status::Failed { message, location } => {
+                        ui.colored_label(egui::Color32::RED, "Error:");
+                        if let Some(location) = location {
+                            ui.strong(format!("at {}", location));
+                        }
+                        ui.label(message.as_str());
+                    }
+                }
+                dismiss = ui.small_button("Dismiss").clicked();
+            });
+        });
+
+        if dismiss {
+            *self = Status::Idle;
+        }
+    }
+}
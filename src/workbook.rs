@@ -0,0 +1,264 @@
+use anyhow::{anyhow, bail, Context, Result};
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use chrono::{Duration, NaiveDate};
+use csv::{Position, StringRecord};
+use std::path::Path;
+
+/// The extensions of the workbooks read in place of a CSV file.
+pub const EXTENSIONS: [&str; 3] = ["xlsx", "xlsm", "xls"];
+
+/// The digits of a UPC-A, which a UPC stored as a number is padded back to.
+const UPC_DIGITS: usize = 12;
+
+/// The rows at the top of a sheet searched for its header row.
+const HEADER_SEARCH: usize = 20;
+
+/// Returns `true` if the file at `path` is a workbook, by its extension.
+pub fn is_workbook(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Returns the names of the sheets of the workbook at `path`, in order.
+pub fn sheet_names(path: &Path) -> Result<Vec<String>> {
+    let workbook = open_workbook_auto(path)
+        .with_context(|| format!("Failed to open the workbook {}", path.display()))?;
+    Ok(workbook.sheet_names().to_vec())
+}
+
+/// A sheet of a workbook read into memory.
+///
+/// Rows and cells keep their place in the sheet, so row 1 is at index 0 and column `A` is the
+/// first cell of each row, and every row has the same number of cells.
+#[derive(Debug, Clone, Default)]
+pub struct Sheet {
+    rows: Vec<Vec<Data>>,
+}
+
+impl Sheet {
+    /// Reads the sheet called `name` of the workbook at `path`, or its first sheet.
+    pub fn open(path: &Path, name: Option<&str>) -> Result<Sheet> {
+        let mut workbook = open_workbook_auto(path)
+            .with_context(|| format!("Failed to open the workbook {}", path.display()))?;
+        let range = match name {
+            Some(name) => {
+                let names = workbook.sheet_names().to_vec();
+                if !names.iter().any(|sheet| sheet == name) {
+                    bail!(
+                        "The workbook {} has no sheet `{}`, its sheets are: {}",
+                        path.display(),
+                        name,
+                        names.join(", ")
+                    );
+                }
+                workbook.worksheet_range(name)?
+            }
+            None => workbook
+                .worksheet_range_at(0)
+                .ok_or_else(|| anyhow!("The workbook {} has no sheets", path.display()))??,
+        };
+
+        Ok(Sheet::from_range(&range))
+    }
+
+    /// Lays the cells of `range` out from the first row and column of the sheet.
+    fn from_range(range: &Range<Data>) -> Sheet {
+        let (top, left) = range.start().unwrap_or_default();
+        let width = left as usize + range.width();
+
+        let mut rows = vec![vec![Data::Empty; width]; top as usize];
+        for cells in range.rows() {
+            let mut row = vec![Data::Empty; left as usize];
+            row.extend(cells.iter().cloned());
+            rows.push(row);
+        }
+        Sheet { rows }
+    }
+
+    /// Returns the index of the header row, the first of the top rows for which `is_header` is
+    /// `true`, or else the first row that isn't empty.
+    pub fn find_header(&self, is_header: impl Fn(&StringRecord) -> bool) -> usize {
+        let filled: Vec<usize> = (0..self.rows.len())
+            .filter(|index| !is_empty(&self.rows[*index]))
+            .take(HEADER_SEARCH)
+            .collect();
+
+        filled
+            .iter()
+            .copied()
+            .find(|index| is_header(&self.record(*index, None)))
+            .or_else(|| filled.first().copied())
+            .unwrap_or_default()
+    }
+
+    /// Returns the text of the row at `index`, positioned at the line of its row number.
+    ///
+    /// A number in the `upc` column, by index, is padded with the leading zeros Excel drops
+    /// from a UPC stored as a number.
+    pub fn record(&self, index: usize, upc: Option<usize>) -> StringRecord {
+        let Some(row) = self.rows.get(index) else {
+            return StringRecord::new();
+        };
+
+        let mut record: StringRecord = row
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| match (cell, Some(column) == upc) {
+                (Data::Int(_) | Data::Float(_), true) => {
+                    format!("{:0>width$}", text(cell), width = UPC_DIGITS)
+                }
+                _ => text(cell),
+            })
+            .collect();
+
+        let mut position = Position::new();
+        position.set_line(index as u64 + 1).set_record(index as u64);
+        record.set_position(Some(position));
+        record
+    }
+
+    /// Returns the rows below the row at `header` that aren't empty, see [Sheet::record].
+    pub fn records(&self, header: usize, upc: Option<usize>) -> Vec<StringRecord> {
+        (header + 1..self.rows.len())
+            .filter(|index| !is_empty(&self.rows[*index]))
+            .map(|index| self.record(index, upc))
+            .collect()
+    }
+}
+
+/// Returns `true` if every cell of `row` is empty or blank.
+fn is_empty(row: &[Data]) -> bool {
+    row.iter().all(|cell| text(cell).trim().is_empty())
+}
+
+/// Returns the text of `cell` as a CSV export of the sheet would hold it: whole numbers
+/// without a decimal point, and dates as `YYYY-MM-DD`.
+fn text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(date) => {
+            // Excel counts days from the last day of 1899, with the time of day as a fraction.
+            let days = date.as_f64();
+            let epoch = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap_or_default();
+            let datetime = epoch.and_hms_opt(0, 0, 0).unwrap_or_default()
+                + Duration::seconds((days * 86_400.0).round() as i64);
+            match days.fract() == 0.0 {
+                true => datetime.format("%Y-%m-%d").to_string(),
+                false => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+            }
+        }
+        Data::Bool(value) => value.to_string().to_uppercase(),
+        // Floats are written out in full, without an exponent.
+        cell => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+
+    /// Writes a workbook whose `Order` sheet has a title above its header, as exports often do.
+    fn workbook(path: &Path) {
+        let mut workbook = Workbook::new();
+        workbook.add_worksheet().set_name("Notes").unwrap();
+        let sheet = workbook.add_worksheet().set_name("Order").unwrap();
+        sheet.write(0, 0, "Purchase order po14423").unwrap();
+        sheet.write_row(2, 1, ["Po", "Upc", "Qty", "Ship"]).unwrap();
+        sheet.write(3, 1, "po14423-001").unwrap();
+        sheet.write(3, 2, 614141000012_f64).unwrap();
+        sheet.write(3, 3, 2).unwrap();
+        let date = ExcelDateTime::from_ymd(2022, 7, 1).unwrap();
+        let format = Format::new().set_num_format("yyyy-mm-dd");
+        sheet
+            .write_datetime_with_format(3, 4, &date, &format)
+            .unwrap();
+        sheet.write(5, 1, "po14423-002").unwrap();
+        sheet.write(5, 2, "000614141000029").unwrap();
+        sheet.write(5, 3, 1.5).unwrap();
+        workbook.save(path).unwrap();
+    }
+
+    #[test]
+    fn reads_rows_below_the_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.xlsx");
+        workbook(&path);
+        assert_eq!(sheet_names(&path).unwrap(), ["Notes", "Order"]);
+
+        let sheet = Sheet::open(&path, Some("Order")).unwrap();
+        let header = sheet.find_header(|record| record.iter().any(|cell| cell == "Upc"));
+        assert_eq!(header, 2);
+        assert_eq!(
+            sheet.record(header, None),
+            StringRecord::from(vec!["", "Po", "Upc", "Qty", "Ship"])
+        );
+
+        let records = sheet.records(header, Some(2));
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0],
+            StringRecord::from(vec!["", "po14423-001", "614141000012", "2", "2022-07-01"])
+        );
+        assert_eq!(
+            records[0].position().map(|position| position.line()),
+            Some(4)
+        );
+        assert_eq!(
+            records[1],
+            StringRecord::from(vec!["", "po14423-002", "000614141000029", "1.5", ""])
+        );
+        assert_eq!(
+            records[1].position().map(|position| position.line()),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn pads_upcs_stored_as_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.xlsx");
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet();
+        sheet.write_row(0, 0, ["Upc", "Qty"]).unwrap();
+        sheet.write(1, 0, 12345678905_f64).unwrap();
+        sheet.write(1, 1, 3).unwrap();
+        workbook.save(&path).unwrap();
+
+        let sheet = Sheet::open(&path, None).unwrap();
+        assert_eq!(sheet.find_header(|_| false), 0);
+        assert_eq!(
+            sheet.records(0, Some(0)),
+            [StringRecord::from(vec!["012345678905", "3"])]
+        );
+        assert_eq!(
+            sheet.records(0, None),
+            [StringRecord::from(vec!["12345678905", "3"])]
+        );
+    }
+
+    #[test]
+    fn names_the_sheets_of_a_workbook_without_the_sheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.xlsx");
+        workbook(&path);
+
+        let error = Sheet::open(&path, Some("Lines")).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("has no sheet `Lines`, its sheets are: Notes, Order"));
+        assert!(Sheet::open(&dir.path().join("missing.xlsx"), None).is_err());
+    }
+
+    #[test]
+    fn knows_workbooks_by_extension() {
+        assert!(is_workbook(Path::new("order.XLSX")));
+        assert!(is_workbook(Path::new("order.xls")));
+        assert!(!is_workbook(Path::new("order.csv")));
+        assert!(!is_workbook(Path::new("xlsx")));
+    }
+}
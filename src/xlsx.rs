@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use std::path::Path;
+
+/// The longest name Excel allows for a sheet.
+const SHEET_NAME_LENGTH: usize = 31;
+
+/// How the files of the lines of each store, or group of stores, are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ArgEnum)]
+pub enum FileFormat {
+    /// A CSV file for each group.
+    #[default]
+    Csv,
+    /// An XLSX workbook for each group.
+    Xlsx,
+    /// An XLSX workbook for each order, with a sheet for each group.
+    XlsxSheets,
+}
+
+impl FileFormat {
+    pub const ALL: [FileFormat; 3] = [FileFormat::Csv, FileFormat::Xlsx, FileFormat::XlsxSheets];
+
+    /// Returns the name of the format shown in GUI mode.
+    pub fn label(&self) -> &str {
+        match self {
+            FileFormat::Csv => "CSV files",
+            FileFormat::Xlsx => "XLSX workbooks",
+            FileFormat::XlsxSheets => "One XLSX, a sheet each",
+        }
+    }
+
+    /// Returns the extension of the files written in this format.
+    pub fn extension(&self) -> &str {
+        match self {
+            FileFormat::Csv => "csv",
+            FileFormat::Xlsx | FileFormat::XlsxSheets => "xlsx",
+        }
+    }
+}
+
+/// A sheet of a workbook written row by row under a bold header row, which stays in view as
+/// the sheet is scrolled.
+///
+/// Cells are stored as text, so UPCs and store numbers keep their leading zeros and aren't
+/// shown in scientific notation, except in the columns set with [SheetWriter::numbers].
+pub struct SheetWriter {
+    worksheet: Worksheet,
+    /// The row the next row is written to.
+    row: u32,
+    /// The columns holding numbers, by index.
+    numbers: Vec<usize>,
+    text: Format,
+    bold: Format,
+}
+
+impl SheetWriter {
+    /// Starts the sheet called `name`, made safe for Excel, with a header row of `headers`.
+    pub fn new(name: &str, headers: &[&str]) -> Result<SheetWriter> {
+        let mut worksheet = Worksheet::new();
+        worksheet
+            .set_name(sheet_name(name))
+            .with_context(|| format!("`{}` can't be the name of a sheet", name))?;
+        worksheet.set_freeze_panes(1, 0)?;
+
+        let mut sheet = SheetWriter {
+            worksheet,
+            row: 0,
+            numbers: vec![],
+            text: Format::new().set_num_format("@"),
+            bold: Format::new().set_bold(),
+        };
+        let bold = sheet.bold.clone();
+        sheet.write_cells(headers, &bold)?;
+        Ok(sheet)
+    }
+
+    /// Returns the name of the sheet.
+    pub fn name(&self) -> String {
+        self.worksheet.name()
+    }
+
+    /// Stores the cells of the columns at `indices` as numbers, where they hold one.
+    pub fn numbers(mut self, indices: impl IntoIterator<Item = usize>) -> SheetWriter {
+        self.numbers = indices.into_iter().collect();
+        self
+    }
+
+    /// Writes a row of `cells`.
+    pub fn write_row<S: AsRef<str>>(&mut self, cells: &[S]) -> Result<()> {
+        let text = self.text.clone();
+        self.write_cells(cells, &text)
+    }
+
+    /// Writes a row of `cells` in bold, e.g. the totals under the rows of a table.
+    pub fn write_totals<S: AsRef<str>>(&mut self, cells: &[S]) -> Result<()> {
+        let bold = self.bold.clone();
+        self.write_cells(cells, &bold)
+    }
+
+    fn write_cells<S: AsRef<str>>(&mut self, cells: &[S], format: &Format) -> Result<()> {
+        for (index, cell) in cells.iter().enumerate() {
+            let cell = cell.as_ref();
+            let column = index as u16;
+            match cell.parse::<f64>() {
+                Ok(number) if self.row > 0 && self.numbers.contains(&index) => {
+                    self.worksheet
+                        .write_number_with_format(self.row, column, number, format)?;
+                }
+                _ => {
+                    self.worksheet
+                        .write_string_with_format(self.row, column, cell, format)?;
+                }
+            }
+        }
+        self.row += 1;
+        Ok(())
+    }
+}
+
+/// Writes a workbook of `sheets`, in order, to `path`.
+pub fn save(sheets: Vec<SheetWriter>, path: &Path) -> Result<()> {
+    workbook(sheets)
+        .save(path)
+        .with_context(|| format!("Failed to write the workbook {}", path.display()))
+}
+
+/// Returns the bytes of an XLSX file of a workbook of `sheets`, in order.
+pub fn to_bytes(sheets: Vec<SheetWriter>) -> Result<Vec<u8>> {
+    Ok(workbook(sheets).save_to_buffer()?)
+}
+
+fn workbook(sheets: Vec<SheetWriter>) -> Workbook {
+    let mut workbook = Workbook::new();
+    for mut sheet in sheets {
+        sheet.worksheet.autofit();
+        workbook.push_worksheet(sheet.worksheet);
+    }
+    workbook
+}
+
+/// Returns `name` as the name of a sheet, see [sheet_name], that none of the sheets called
+/// `taken` has. A name cut to the same characters as a name taken ends in `~2`, `~3` and so on.
+pub fn unique_name(name: &str, taken: &[String]) -> String {
+    let is_taken = |name: &str| {
+        taken
+            .iter()
+            .any(|taken| taken.to_lowercase() == name.to_lowercase())
+    };
+    let name = sheet_name(name);
+    let mut unique = name.clone();
+    let mut number = 1;
+    while is_taken(&unique) {
+        number += 1;
+        let suffix = format!("~{}", number);
+        unique = name
+            .chars()
+            .take(SHEET_NAME_LENGTH - suffix.len())
+            .chain(suffix.chars())
+            .collect();
+    }
+    unique
+}
+
+/// Returns `name` as the name of a sheet, with the characters Excel doesn't allow replaced and
+/// cut to the longest name Excel allows.
+fn sheet_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '-',
+            c => c,
+        })
+        .take(SHEET_NAME_LENGTH)
+        .collect();
+    name.trim_matches('\'').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_sheet_names_to_fit() {
+        assert_eq!(
+            sheet_name("PO2024-000123456_DC_Northeast-Distribution-1"),
+            "PO2024-000123456_DC_Northeast-D"
+        );
+        assert_eq!(sheet_name("'Store: 001/002'"), "Store- 001-002");
+    }
+
+    #[test]
+    fn tells_apart_names_cut_the_same() {
+        let mut taken = vec![];
+        for dc in [
+            "Northeast-Distribution-1",
+            "Northeast-Distribution-2",
+            "North",
+        ] {
+            taken.push(unique_name(&format!("PO2024-000123456_DC_{}", dc), &taken));
+        }
+        assert_eq!(
+            taken,
+            [
+                "PO2024-000123456_DC_Northeast-D",
+                "PO2024-000123456_DC_Northeast~2",
+                "PO2024-000123456_DC_North",
+            ]
+        );
+        assert_eq!(
+            unique_name("po2024-000123456_dc_northeast-d", &taken),
+            "po2024-000123456_dc_northeast~3"
+        );
+    }
+
+    #[test]
+    fn saves_sheets_cut_the_same() {
+        let mut sheets: Vec<SheetWriter> = vec![];
+        for dc in ["Northeast-Distribution-1", "Northeast-Distribution-2"] {
+            let taken: Vec<String> = sheets.iter().map(SheetWriter::name).collect();
+            let name = unique_name(&format!("PO2024-000123456_DC_{}", dc), &taken);
+            sheets.push(SheetWriter::new(&name, &["UPC"]).unwrap());
+        }
+        assert!(to_bytes(sheets).is_ok());
+    }
+}
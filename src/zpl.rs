@@ -0,0 +1,197 @@
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use std::path::Path;
+
+/// The label LISA prints when no template is configured, a 2x1 inch label at 203 dpi.
+pub const DEFAULT_TEMPLATE: &str = "^XA
+^CI28
+^FO20,15^A0N,24,24^FD{style} {style_desc}^FS
+^FO20,45^A0N,22,22^FD{color} / {size}^FS
+^FO20,72^A0N,20,20^FDPO {po}  Store {store}^FS
+^FO40,100^BY2^BUN,60,Y,N,Y^FD{upc}^FS
+^XZ
+";
+
+/// The default label when EPCs are encoded, which also writes the EPC to the tag of the label.
+pub const DEFAULT_RFID_TEMPLATE: &str = "^XA
+^CI28
+^RS8
+^RFW,H^FD{epc}^FS
+^FO20,15^A0N,24,24^FD{style} {style_desc}^FS
+^FO20,45^A0N,22,22^FD{color} / {size}^FS
+^FO20,72^A0N,20,20^FDPO {po}  Store {store}^FS
+^FO40,100^BY2^BUN,60,Y,N,Y^FD{upc}^FS
+^XZ
+";
+
+/// The placeholders a template may use.
+pub const PLACEHOLDERS: [&str; 9] = [
+    "po",
+    "store",
+    "style",
+    "style_desc",
+    "color",
+    "color_code",
+    "size",
+    "upc",
+    "epc",
+];
+
+/// The values printed on one label.
+#[derive(Debug, Clone, Copy)]
+pub struct Label<'a> {
+    pub po: &'a str,
+    pub store: &'a str,
+    pub style: &'a str,
+    pub style_desc: &'a str,
+    /// The color description.
+    pub color: &'a str,
+    pub color_code: &'a str,
+    pub size: &'a str,
+    pub upc: &'a str,
+    /// The EPC written to the tag, empty when EPCs aren't encoded.
+    pub epc: &'a str,
+}
+
+impl Label<'_> {
+    fn value(&self, placeholder: &str) -> &str {
+        match placeholder {
+            "po" => self.po,
+            "store" => self.store,
+            "style" => self.style,
+            "style_desc" => self.style_desc,
+            "color" => self.color,
+            "color_code" => self.color_code,
+            "size" => self.size,
+            "upc" => self.upc,
+            "epc" => self.epc,
+            _ => "",
+        }
+    }
+}
+
+/// A ZPL II label layout with placeholders, e.g. `^FD{upc}^FS`, filled in for each label.
+///
+/// The placeholders are `{po}`, `{store}`, `{style}`, `{style_desc}`, `{color}`, `{color_code}`,
+/// `{size}`, `{upc}` and `{epc}`. The template should hold a single label, from `^XA` to `^XZ`.
+#[derive(Debug, Clone)]
+pub struct ZplTemplate {
+    text: String,
+    placeholder: Regex,
+}
+
+fn placeholder() -> Regex {
+    Regex::new(r"\{([a-z_]+)\}").expect("The placeholder pattern is valid")
+}
+
+impl Default for ZplTemplate {
+    fn default() -> Self {
+        ZplTemplate::new(DEFAULT_TEMPLATE).expect("The default template is valid")
+    }
+}
+
+impl ZplTemplate {
+    /// Checks that every placeholder of `text` is known.
+    pub fn new(text: &str) -> Result<ZplTemplate> {
+        let placeholder = placeholder();
+        for captures in placeholder.captures_iter(text) {
+            if !PLACEHOLDERS.contains(&&captures[1]) {
+                bail!(
+                    "Unknown placeholder `{}`, expected one of {{{}}}",
+                    &captures[0],
+                    PLACEHOLDERS.join("}, {")
+                );
+            }
+        }
+        if !text.contains("^XA") || !text.contains("^XZ") {
+            bail!("A label template must start with `^XA` and end with `^XZ`");
+        }
+
+        Ok(ZplTemplate {
+            text: text.to_owned(),
+            placeholder,
+        })
+    }
+
+    /// Returns `true` if the template uses the placeholder `name`, e.g. `epc`.
+    pub fn uses(&self, name: &str) -> bool {
+        self.placeholder
+            .captures_iter(&self.text)
+            .any(|captures| &captures[1] == name)
+    }
+
+    /// Reads the template at `path`.
+    pub fn load(path: &Path) -> Result<ZplTemplate> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the label template {}", path.display()))?;
+
+        ZplTemplate::new(&text)
+            .with_context(|| format!("Invalid label template {}", path.display()))
+    }
+
+    /// Returns the ZPL of `label`, each value made safe with [field].
+    pub fn render(&self, label: &Label) -> String {
+        self.placeholder
+            .replace_all(&self.text, |captures: &Captures| {
+                field(label.value(&captures[1]))
+            })
+            .into_owned()
+    }
+}
+
+/// Returns `value` to be printed in a ZPL field.
+///
+/// `^` and `~` start ZPL commands, so they are left out of the value.
+pub fn field(value: &str) -> String {
+    value.replace(['^', '~'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABEL: Label = Label {
+        po: "po14423-001",
+        store: "001",
+        style: "TS100",
+        style_desc: "TEE ^FS~JA",
+        color: "Black",
+        color_code: "001",
+        size: "M",
+        upc: "614141234561",
+        epc: "30383BF98078900000000001",
+    };
+
+    #[test]
+    fn fills_in_every_placeholder() {
+        let template = ZplTemplate::new(
+            "^XA^FD{po}|{store}|{style}|{color}|{color_code}|{size}|{upc}|{epc}^FS^XZ",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&LABEL),
+            "^XA^FDpo14423-001|001|TS100|Black|001|M|614141234561|30383BF98078900000000001^FS^XZ"
+        );
+    }
+
+    #[test]
+    fn leaves_zpl_commands_out_of_values() {
+        let template = ZplTemplate::new("^XA^FD{style_desc}^FS^XZ").unwrap();
+        assert_eq!(template.render(&LABEL), "^XA^FDTEE FSJA^FS^XZ");
+    }
+
+    #[test]
+    fn knows_the_placeholders_it_uses() {
+        assert!(!ZplTemplate::default().uses("epc"));
+        assert!(ZplTemplate::new(DEFAULT_RFID_TEMPLATE).unwrap().uses("epc"));
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        let error = ZplTemplate::new("^XA^FD{price}^FS^XZ").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unknown placeholder `{price}`"));
+        assert!(ZplTemplate::new("^FD{upc}^FS").is_err());
+    }
+}